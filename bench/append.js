@@ -2,6 +2,8 @@ const { Benchmark } = require('tiny-benchy');
 const { SourceMap } = require('./setup');
 const AngularSourceMap = require('./maps/angular');
 
+const MODULE_COUNT = 500;
+
 const setup = () => {
   let sourcemapInstance = new SourceMap();
   sourcemapInstance.addVLQMap(AngularSourceMap);
@@ -15,6 +17,33 @@ const setup = () => {
   };
 };
 
+// A bundle is usually joined from many small module maps that share a few sources and names
+const setupModules = () => {
+  let moduleMaps = [];
+  for (let i = 0; i < MODULE_COUNT; i++) {
+    let moduleMap = new SourceMap();
+    moduleMap.addIndexedMappings([
+      {
+        generated: { line: 1, column: 0 },
+        original: { line: 1, column: 0 },
+        source: `src/module-${i}.js`,
+        name: `export${i % 50}`,
+      },
+      {
+        generated: { line: 2, column: 4 },
+        original: { line: 3, column: 0 },
+        source: 'src/shared.js',
+      },
+    ]);
+    moduleMaps.push(moduleMap);
+  }
+
+  return {
+    sourcemapInstance: new SourceMap(),
+    moduleMaps,
+  };
+};
+
 exports.append = function () {
   const suite = new Benchmark({
     iterations: 10,
@@ -28,5 +57,15 @@ exports.append = function () {
     { setup }
   );
 
+  suite.add(
+    `append#addSourceMap ${MODULE_COUNT} small maps`,
+    ({ sourcemapInstance, moduleMaps }) => {
+      for (let i = 0; i < moduleMaps.length; i++) {
+        sourcemapInstance.addSourceMap(moduleMaps[i], i * 2);
+      }
+    },
+    { setup: setupModules }
+  );
+
   return suite.run();
 };
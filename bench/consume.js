@@ -16,6 +16,23 @@ const MAPPINGS = new Array(100).fill('').map((item, index) => {
   };
 });
 
+// Mappings spread over many lines, like in unminified output
+const SORTED_MAPPINGS = new Array(10000).fill('').map((item, index) => {
+  return {
+    source: 'index.js',
+    original: {
+      line: index + 1,
+      column: 0,
+    },
+    generated: {
+      line: Math.floor(index / 10) + 1,
+      column: 4 * (index % 10),
+    },
+  };
+});
+
+const UNSORTED_MAPPINGS = SORTED_MAPPINGS.slice().reverse();
+
 exports.consume = function () {
   const suite = new Benchmark({
     iterations: 100,
@@ -62,5 +79,18 @@ exports.consume = function () {
     map.delete();
   });
 
+  // Sorted input is added through extend_sorted, the reversed input goes through add_mapping one by one
+  suite.add('consume#consume sorted JS Mappings', () => {
+    let map = new SourceMap();
+    map.addIndexedMappings(SORTED_MAPPINGS);
+    map.delete();
+  });
+
+  suite.add('consume#consume unsorted JS Mappings', () => {
+    let map = new SourceMap();
+    map.addIndexedMappings(UNSORTED_MAPPINGS);
+    map.delete();
+  });
+
   return suite.run();
 };
@@ -27,6 +27,7 @@ export type VLQMap = Readonly<{
   version?: number;
   file?: string;
   sourceRoot?: string;
+  debugId?: string;
 }>;
 
 /**
@@ -51,6 +52,15 @@ export type SourceMapStringifyOptions = {
       readFile(path: string, encoding: string): Promise<string>;
   };
   format?: 'inline' | 'string' | 'object';
+  omitEmpty?: boolean;
+};
+
+/**
+* A serialised source map without embedded sources content and the content of each source
+*/
+export type SplitSourceMap = {
+  map: string | VLQMap;
+  sourcesContent: Array<[string, string | null]>;
 };
 
 /**
@@ -63,6 +73,17 @@ export type GenerateEmptyMapOptions = {
   lineOffset?: number;
 };
 
+/**
+* Counts describing the contents of a source map
+*/
+export type SourceMapStats = {
+  sources: number;
+  names: number;
+  mappings: number;
+  lines: number;
+  withContent: number;
+};
+
 /**
 * A source map to assist in debugging during development
 */
@@ -85,6 +106,9 @@ export default class SourceMap {
   getName(index: number): string;
   extends(buffer: Buffer): SourceMap;
   getMap(): ParsedMap;
+  getStats(): SourceMapStats;
+  getDebugId(): string | null;
+  setDebugId(debugId: string | null): void;
   findClosestMapping(line: number, column: number): IndexedMapping<string> | undefined;
   offsetLines(line: number, lineOffset: number): IndexedMapping<string> | undefined;
   offsetColumns(line: number, column: number, columnOffset: number): IndexedMapping<string> | undefined;
@@ -92,4 +116,5 @@ export default class SourceMap {
   toVLQ(): VLQMap;
   delete(): void;
   stringify(options: SourceMapStringifyOptions): Promise<string | VLQMap>;
+  stringifySplit(options: SourceMapStringifyOptions): Promise<SplitSourceMap>;
 }
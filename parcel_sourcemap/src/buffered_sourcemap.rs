@@ -0,0 +1,113 @@
+use crate::mapping::Mapping;
+use crate::mapping_line::MappingLine;
+use crate::sourcemap_error::{SourceMapError, SourceMapErrorType};
+use crate::{archived_buffer, deserialize_strings, ArchivedSourceMapInner};
+use rkyv::{de::deserializers::AllocDeserializer, Deserialize};
+
+// A read-only view over a buffer created by SourceMap::to_buffer. Sources and names are decoded
+// when the view is created, mapping lines are only decoded when a position on them gets resolved,
+// which is a lot cheaper than from_buffer when only a few positions of a big sourcemap are needed.
+pub struct BufferedSourceMap<'a> {
+    pub project_root: String,
+    sources: Vec<String>,
+    names: Vec<String>,
+    archived: &'a ArchivedSourceMapInner,
+}
+
+impl<'a> BufferedSourceMap<'a> {
+    pub fn new(project_root: &str, buf: &'a [u8]) -> Result<Self, SourceMapError> {
+        let archived = archived_buffer(buf)?;
+        Ok(Self {
+            project_root: String::from(project_root),
+            sources: deserialize_strings(&archived.sources),
+            names: deserialize_strings(&archived.names),
+            archived,
+        })
+    }
+
+    pub fn get_sources(&self) -> &Vec<String> {
+        &self.sources
+    }
+
+    pub fn get_names(&self) -> &Vec<String> {
+        &self.names
+    }
+
+    pub fn get_source_content(&self, index: u32) -> Result<&str, SourceMapError> {
+        self.archived
+            .sources_content
+            .get(index as usize)
+            .map(|v| v.as_str())
+            .ok_or_else(|| SourceMapError::new(SourceMapErrorType::SourceOutOfRange))
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.archived.mapping_lines.len()
+    }
+
+    // Decode the mappings of a single generated line, None if the line is past the last line
+    pub fn decode_line(&self, generated_line: u32) -> Result<Option<MappingLine>, SourceMapError> {
+        match self.archived.mapping_lines.get(generated_line as usize) {
+            Some(line) => Ok(Some(line.deserialize(&mut AllocDeserializer)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Same as SourceMap::find_closest_mapping, only the requested line gets decoded
+    pub fn find_closest_mapping(
+        &self,
+        generated_line: u32,
+        generated_column: u32,
+    ) -> Result<Option<Mapping>, SourceMapError> {
+        let mapping = self
+            .decode_line(generated_line)?
+            .and_then(|mut line| line.find_closest_mapping(generated_column))
+            .map(|line_mapping| Mapping {
+                generated_line,
+                generated_column: line_mapping.generated_column,
+                original: line_mapping.original,
+            });
+        Ok(mapping)
+    }
+}
+
+#[test]
+fn test_buffered_find_closest_mapping() {
+    use crate::{OriginalLocation, SourceMap};
+    use rkyv::AlignedVec;
+
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("big.js");
+    map.set_source_content(source as usize, "let big;").unwrap();
+    let name = map.add_name("big");
+    for generated_line in 0..2_000 {
+        for column in 0..20 {
+            map.add_mapping(
+                generated_line,
+                column * 5,
+                Some(OriginalLocation::new(
+                    generated_line,
+                    column,
+                    source,
+                    if column == 3 { Some(name) } else { None },
+                )),
+            );
+        }
+    }
+
+    let mut buffer = AlignedVec::new();
+    map.to_buffer(&mut buffer).unwrap();
+    let buffered = BufferedSourceMap::new("/", &buffer).unwrap();
+    assert_eq!(buffered.get_sources(), &vec![String::from("big.js")]);
+    assert_eq!(buffered.get_names(), &vec![String::from("big")]);
+    assert_eq!(buffered.get_source_content(0).unwrap(), "let big;");
+    assert_eq!(buffered.line_count(), 2_000);
+
+    let mapping = buffered.find_closest_mapping(1_234, 17).unwrap();
+    assert_eq!(mapping, map.find_closest_mapping(1_234, 17));
+    assert_eq!(
+        mapping.unwrap().original,
+        Some(OriginalLocation::new(1_234, 3, 0, Some(0)))
+    );
+    assert_eq!(buffered.find_closest_mapping(5_000, 0).unwrap(), None);
+}
@@ -1,25 +1,38 @@
 #![deny(clippy::all)]
 
+pub mod buffered_sourcemap;
 pub mod mapping;
 pub mod mapping_line;
 pub mod sourcemap_error;
 pub mod utils;
 mod vlq_utils;
+pub mod vlq_writer;
 
-use crate::utils::make_relative_path;
+use crate::utils::{
+    file_url_from_path, is_abs_path, make_absolute_path, make_relative_path, normalize_path,
+};
+pub use buffered_sourcemap::BufferedSourceMap;
 pub use mapping::{Mapping, OriginalLocation};
-use mapping_line::MappingLine;
+pub use mapping_line::ColumnUnderflow;
+use mapping_line::{LineMapping, MappingLine};
 pub use sourcemap_error::{SourceMapError, SourceMapErrorType};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fmt;
 use std::io;
+use std::sync::Arc;
 
 use rkyv::{
     archived_root,
     de::deserializers::AllocDeserializer,
     ser::{serializers::AlignedSerializer, Serializer},
+    std_impl::{ArchivedString, ArchivedVec},
     AlignedVec, Archive, Deserialize, Serialize,
 };
 
-use vlq_utils::{is_mapping_separator, read_relative_vlq};
+pub use vlq_utils::{decode_vlq, encode_vlq};
+use vlq_utils::{is_mapping_separator, read_relative_vlq, ReadBytes};
+pub use vlq_writer::VlqWriter;
 
 #[derive(Archive, Serialize, Deserialize, Debug, Default)]
 pub struct SourceMapInner {
@@ -27,19 +40,190 @@ pub struct SourceMapInner {
     pub sources_content: Vec<String>,
     pub names: Vec<String>,
     pub mapping_lines: Vec<MappingLine>,
+    pub debug_id: Option<String>,
+}
+
+// Buffers created by to_buffer start with this magic and version, padded to 16 bytes so the archive after it stays aligned.
+// The archive is read without validation, so a buffer with another layout (e.g. from an older release) has to be
+// rejected before it gets read. The magic starts with a byte that can't start a utf-8 string, older buffers
+// start with the bytes of the first source.
+const BUFFER_MAGIC: &[u8; 4] = b"\xffPSM";
+const BUFFER_VERSION: u32 = 1;
+const BUFFER_HEADER_SIZE: usize = 16;
+
+// Check the header of a buffer created by to_buffer and get the archived sourcemap after it
+fn archived_buffer(buf: &[u8]) -> Result<&ArchivedSourceMapInner, SourceMapError> {
+    if buf.len() < BUFFER_HEADER_SIZE || &buf[0..4] != BUFFER_MAGIC {
+        return Err(SourceMapError::new_with_reason(
+            SourceMapErrorType::BufferError,
+            "invalid buffer header",
+        ));
+    }
+
+    let version = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+    if version != BUFFER_VERSION {
+        return Err(SourceMapError::new_with_reason(
+            SourceMapErrorType::BufferError,
+            "unsupported buffer version",
+        ));
+    }
+
+    Ok(unsafe { archived_root::<SourceMapInner>(&buf[BUFFER_HEADER_SIZE..]) })
+}
+
+// Incremental buffer chunks start with this magic and version, followed by the payload length (u32 LE) and the payload
+const BUFFER_CHUNK_MAGIC: &[u8; 4] = b"PSMC";
+const BUFFER_CHUNK_VERSION: u32 = 1;
+const BUFFER_CHUNK_HEADER_SIZE: usize = 12;
+
+// Payload of an incremental buffer chunk, the full sources and names tables and all mapping lines starting at since_line
+#[derive(Archive, Serialize, Deserialize)]
+struct SourceMapChunk {
+    since_line: u32,
+    sources: Vec<String>,
+    sources_content: Vec<String>,
+    names: Vec<String>,
+    mapping_lines: Vec<MappingLine>,
+}
+
+// rkyv deserializes an empty archived string into a null pointer, so strings
+// get copied out of the archive instead of going through the deserializer
+fn deserialize_strings(archived: &ArchivedVec<ArchivedString>) -> Vec<String> {
+    archived.iter().map(|s| String::from(s.as_str())).collect()
+}
+
+// Add items to a list without duplicates and return the index of every item in the list.
+// Small appends just scan the list, bigger ones hash it once so joining large lists doesn't go quadratic.
+// Items that repeat within items itself are deduplicated as well.
+fn append_deduplicated(list: &mut Vec<String>, items: Vec<String>) -> Vec<u32> {
+    if items.len() * (list.len() + items.len()) <= 1024 {
+        return items
+            .into_iter()
+            .map(|item| match list.iter().position(|s| item.eq(s)) {
+                Some(i) => i as u32,
+                None => {
+                    list.push(item);
+                    (list.len() - 1) as u32
+                }
+            })
+            .collect();
+    }
+
+    let mut added: Vec<String> = Vec::new();
+    let indexes = {
+        let mut lookup: HashMap<&str, u32> = HashMap::with_capacity(list.len() + items.len());
+        for (i, s) in list.iter().enumerate().rev() {
+            lookup.insert(s.as_str(), i as u32);
+        }
+
+        let mut indexes = Vec::with_capacity(items.len());
+        for item in items.iter() {
+            let next_index = (list.len() + added.len()) as u32;
+            let index = *lookup.entry(item.as_str()).or_insert(next_index);
+            if index == next_index {
+                added.push(item.clone());
+            }
+            indexes.push(index);
+        }
+        indexes
+    };
+
+    list.extend(added);
+    indexes
+}
+
+// Sort mappings by column and keep only the last mapping of every column, which is the one write_vlq writes
+fn sorted_last_per_column(mappings: &mut Vec<LineMapping>, is_sorted: bool) {
+    if !is_sorted {
+        mappings.sort_by_key(|m| m.generated_column);
+    }
+
+    mappings.dedup_by(|next, kept| {
+        if next.generated_column != kept.generated_column {
+            return false;
+        }
+
+        *kept = *next;
+        true
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceMapStats {
+    pub sources: usize,
+    pub names: usize,
+    pub mappings: usize,
+    pub lines: usize,
+    pub with_content: usize,
+}
+
+// The generated range of a mapping and the original range it corresponds to.
+// The generated range ends at the next mapping on the same line, None means it runs until the end of the line.
+// The original range ends at the original position of that next mapping, but only if it points into the same
+// source at or after the start, otherwise the original end can't be inferred and is None.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MappingRange {
+    pub generated_line: u32,
+    pub generated_start_column: u32,
+    pub generated_end_column: Option<u32>,
+    pub original_start: Option<OriginalLocation>,
+    pub original_end: Option<OriginalLocation>,
+}
+
+// How add_name deduplicates names.
+// Exact reuses the index of an identical name, which keeps the names array small.
+// None always appends a new name, which gives every occurrence a stable index of its own
+// at the cost of a bigger names array and no lookup on insert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameDedup {
+    #[default]
+    Exact,
+    None,
+}
+
+// What compose does with a mapping whose intermediate position has no mapping in the composed sourcemap.
+// Unmapped keeps the generated position without an original location, KeepIntermediate keeps pointing
+// at the intermediate source and Drop removes the mapping completely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingMapping {
+    #[default]
+    Unmapped,
+    KeepIntermediate,
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComposeOptions {
+    pub on_missing: MissingMapping,
+}
+
+// Loads the content of a source on demand, it gets called with the source path as stored in the sourcemap
+type LoadContent = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+#[derive(Clone)]
+pub struct ContentLoader(Arc<LoadContent>);
+
+impl fmt::Debug for ContentLoader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ContentLoader")
+    }
 }
 
 #[derive(Debug)]
 pub struct SourceMap {
     pub project_root: String,
+    pub name_dedup: NameDedup,
     inner: SourceMapInner,
+    content_loader: Option<ContentLoader>,
 }
 
 impl SourceMap {
     pub fn new(project_root: &str) -> Self {
         Self {
             project_root: String::from(project_root),
+            name_dedup: NameDedup::default(),
             inner: SourceMapInner::default(),
+            content_loader: None,
         }
     }
 
@@ -67,6 +251,173 @@ impl SourceMap {
         self.inner.mapping_lines[generated_line as usize].add_mapping(generated_column, original);
     }
 
+    // Same as add_mapping but rejects u32::MAX as generated line or column, these are almost always
+    // the result of an underflow somewhere and would break the overflow checks of the offset functions later on
+    pub fn try_add_mapping(
+        &mut self,
+        generated_line: u32,
+        generated_column: u32,
+        original: Option<OriginalLocation>,
+    ) -> Result<(), SourceMapError> {
+        if generated_line == u32::MAX || generated_column == u32::MAX {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::UnexpectedlyBigNumber,
+                "generated line and column have to be smaller than u32::MAX",
+            ));
+        }
+
+        self.add_mapping(generated_line, generated_column, original);
+        Ok(())
+    }
+
+    // Add the mapping only if there is no mapping on its generated position yet, returns whether it got added
+    pub fn add_mapping_if_absent(&mut self, mapping: Mapping) -> bool {
+        if let Some(line) = self
+            .inner
+            .mapping_lines
+            .get_mut(mapping.generated_line as usize)
+        {
+            line.ensure_sorted();
+            if line
+                .mappings
+                .binary_search_by(|m| m.generated_column.cmp(&mapping.generated_column))
+                .is_ok()
+            {
+                return false;
+            }
+        }
+
+        self.add_mapping(
+            mapping.generated_line,
+            mapping.generated_column,
+            mapping.original,
+        );
+        true
+    }
+
+    // Add mappings that are already sorted by generated position,
+    // this skips the per mapping line lookups and keeps every line sorted without having to sort later on
+    pub fn extend_sorted<I>(&mut self, mappings: I)
+    where
+        I: Iterator<Item = Mapping>,
+    {
+        let mut previous_position: Option<(u32, u32)> = None;
+        let mut current_line: Option<&mut MappingLine> = None;
+        for mapping in mappings {
+            let position = (mapping.generated_line, mapping.generated_column);
+            debug_assert!(
+                !matches!(previous_position, Some(previous) if previous > position),
+                "extend_sorted requires mappings sorted by generated position"
+            );
+
+            if previous_position.map(|(line, _)| line) != Some(mapping.generated_line) {
+                self.ensure_lines(mapping.generated_line as usize);
+                current_line = None;
+            }
+
+            let line = match current_line {
+                Some(line) => line,
+                None => &mut self.inner.mapping_lines[mapping.generated_line as usize],
+            };
+            line.add_mapping(mapping.generated_column, mapping.original);
+            current_line = Some(line);
+            previous_position = Some(position);
+        }
+    }
+
+    // Add mappings from a flat array with 6 values per mapping:
+    // generated line, generated column, original line, original column, source and name, -1 means the value is absent.
+    // Input that is already sorted by generated position goes through extend_sorted.
+    pub fn add_indexed_mappings(&mut self, mappings_arr: &[i32]) {
+        let mappings = mappings_arr.chunks_exact(6).map(|mapping| {
+            let (original_line, original_column, original_source, name) =
+                (mapping[2], mapping[3], mapping[4], mapping[5]);
+            Mapping {
+                generated_line: mapping[0] as u32,
+                generated_column: mapping[1] as u32,
+                original: if original_line > -1 && original_column > -1 && original_source > -1 {
+                    Some(OriginalLocation {
+                        original_line: original_line as u32,
+                        original_column: original_column as u32,
+                        source: original_source as u32,
+                        name: if name > -1 { Some(name as u32) } else { None },
+                    })
+                } else {
+                    None
+                },
+            }
+        });
+
+        let is_sorted = mappings_arr
+            .chunks_exact(6)
+            .zip(mappings_arr.chunks_exact(6).skip(1))
+            .all(|(a, b)| (a[0] as u32, a[1] as u32) <= (b[0] as u32, b[1] as u32));
+        if is_sorted {
+            self.extend_sorted(mappings);
+        } else {
+            for mapping in mappings {
+                self.add_mapping(
+                    mapping.generated_line,
+                    mapping.generated_column,
+                    mapping.original,
+                );
+            }
+        }
+    }
+
+    // The mappings as a flat array of [generated_line, generated_column, original_line, original_column, source, name, ...],
+    // absent original fields are -1. This is the same layout add_indexed_mappings takes.
+    pub fn to_indexed_mappings(&self) -> Vec<i32> {
+        let mappings = self.get_mappings();
+        let mut mappings_arr = Vec::with_capacity(mappings.len() * 6);
+        for mapping in mappings.iter() {
+            mappings_arr.push(mapping.generated_line as i32);
+            mappings_arr.push(mapping.generated_column as i32);
+            match mapping.original {
+                Some(original) => {
+                    mappings_arr.push(original.original_line as i32);
+                    mappings_arr.push(original.original_column as i32);
+                    mappings_arr.push(original.source as i32);
+                    mappings_arr.push(original.name.map(|name| name as i32).unwrap_or(-1));
+                }
+                None => mappings_arr.extend_from_slice(&[-1, -1, -1, -1]),
+            }
+        }
+        mappings_arr
+    }
+
+    // Create a sourcemap from a flat indexed mappings array and the sources and names it refers to
+    pub fn from_indexed_mappings(
+        project_root: &str,
+        mappings_arr: &[i32],
+        sources: Vec<String>,
+        names: Vec<String>,
+    ) -> Result<SourceMap, SourceMapError> {
+        let mut sourcemap = SourceMap::new(project_root);
+        let source_indexes = sourcemap.add_sources(sources.iter().map(|s| s.as_str()).collect());
+        let name_indexes = sourcemap.add_names(names.iter().map(|n| n.as_str()).collect());
+
+        let mut remapped_mappings = mappings_arr.to_vec();
+        for mapping in remapped_mappings.chunks_exact_mut(6) {
+            if mapping[4] > -1 {
+                mapping[4] = match source_indexes.get(mapping[4] as usize) {
+                    Some(source_index) => *source_index as i32,
+                    None => return Err(SourceMapError::new(SourceMapErrorType::SourceOutOfRange)),
+                };
+            }
+
+            if mapping[5] > -1 {
+                mapping[5] = match name_indexes.get(mapping[5] as usize) {
+                    Some(name_index) => *name_index as i32,
+                    None => return Err(SourceMapError::new(SourceMapErrorType::NameOutOfRange)),
+                };
+            }
+        }
+
+        sourcemap.add_indexed_mappings(&remapped_mappings);
+        Ok(sourcemap)
+    }
+
     pub fn add_mapping_with_offset(
         &mut self,
         mapping: Mapping,
@@ -131,376 +482,447 @@ impl SourceMap {
         None
     }
 
-    pub fn get_mappings(&self) -> Vec<Mapping> {
-        let mut mappings = Vec::new();
-        for (generated_line, mapping_line) in self.inner.mapping_lines.iter().enumerate() {
-            for mapping in mapping_line.mappings.iter() {
-                mappings.push(Mapping {
-                    generated_line: generated_line as u32,
-                    generated_column: mapping.generated_column,
-                    original: mapping.original,
-                });
-            }
+    // Set or clear the name of the mapping at exactly this generated position
+    pub fn set_mapping_name(
+        &mut self,
+        generated_line: u32,
+        generated_column: u32,
+        name: Option<&str>,
+    ) -> Result<(), SourceMapError> {
+        let mapping_index = self
+            .inner
+            .mapping_lines
+            .get(generated_line as usize)
+            .and_then(|line| {
+                line.mappings
+                    .iter()
+                    .position(|m| m.generated_column == generated_column)
+            })
+            .ok_or_else(|| {
+                SourceMapError::new_with_reason(
+                    SourceMapErrorType::MappingOutOfRange,
+                    "there is no mapping at this generated position",
+                )
+            })?;
+
+        let line = generated_line as usize;
+        if self.inner.mapping_lines[line].mappings[mapping_index]
+            .original
+            .is_none()
+        {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::InvalidMapping,
+                "can't name a mapping without an original position",
+            ));
+        }
+
+        let name_index = name.map(|name| self.add_name(name));
+        if let Some(original) = &mut self.inner.mapping_lines[line].mappings[mapping_index].original
+        {
+            original.name = name_index;
         }
-        mappings
+        Ok(())
     }
 
-    pub fn write_vlq<W>(&mut self, output: &mut W) -> Result<(), SourceMapError>
-    where
-        W: io::Write,
-    {
-        let mut last_generated_line: u32 = 0;
-        let mut previous_source: i64 = 0;
-        let mut previous_original_line: i64 = 0;
-        let mut previous_original_column: i64 = 0;
-        let mut previous_name: i64 = 0;
-
-        for (generated_line, line_content) in self.inner.mapping_lines.iter_mut().enumerate() {
-            let mut previous_generated_column: u32 = 0;
-            let cloned_generated_line = generated_line as u32;
-            if cloned_generated_line > 0 {
-                // Write a ';' for each line between this and last line, way more efficient than storing empty lines or looping...
-                output.write_all(
-                    &b";".repeat((cloned_generated_line - last_generated_line) as usize),
-                )?;
-            }
+    // Resolve many generated positions at once with a single ordered walk, the queries are sorted by position
+    // so every line only gets sorted once and a cursor moves forward through its mappings instead of doing
+    // a binary search per query. The results match find_closest_mapping and are in the same order as the queries.
+    pub fn resolve_batch(&mut self, queries: &[(u32, u32)]) -> Vec<Option<Mapping>> {
+        let mut query_order: Vec<usize> = (0..queries.len()).collect();
+        query_order.sort_by_key(|i| queries[*i]);
 
-            line_content.ensure_sorted();
+        let mut results: Vec<Option<Mapping>> = (0..queries.len()).map(|_| None).collect();
+        let mut current_line: Option<u32> = None;
+        let mut cursor = 0;
+        for i in query_order {
+            let (generated_line, generated_column) = queries[i];
+            let line = match self.inner.mapping_lines.get_mut(generated_line as usize) {
+                Some(line) if !line.mappings.is_empty() => line,
+                _ => continue,
+            };
 
-            let mut is_first_mapping: bool = true;
-            for mapping in &line_content.mappings {
-                let generated_column = mapping.generated_column;
-                let original_location_option = &mapping.original;
-                if !is_first_mapping {
-                    output.write_all(b",")?;
-                }
-
-                vlq::encode(
-                    (generated_column - previous_generated_column) as i64,
-                    output,
-                )?;
-                previous_generated_column = generated_column;
-
-                // Source should only be written if there is any
-                if let Some(original) = &original_location_option {
-                    let original_source = original.source as i64;
-                    vlq::encode(original_source - previous_source, output)?;
-                    previous_source = original_source;
-
-                    let original_line = original.original_line as i64;
-                    vlq::encode((original_line - previous_original_line) as i64, output)?;
-                    previous_original_line = original_line;
-
-                    let original_column = original.original_column as i64;
-                    vlq::encode(original_column - previous_original_column, output)?;
-                    previous_original_column = original_column;
-
-                    if let Some(name) = original.name {
-                        let original_name = name as i64;
-                        vlq::encode(original_name - previous_name, output)?;
-                        previous_name = original_name;
-                    }
-                }
+            if current_line != Some(generated_line) {
+                line.ensure_sorted();
+                current_line = Some(generated_line);
+                cursor = 0;
+            }
 
-                is_first_mapping = false;
+            // A column before the first mapping resolves to the first mapping, like find_closest_mapping
+            while cursor + 1 < line.mappings.len()
+                && line.mappings[cursor + 1].generated_column <= generated_column
+            {
+                cursor += 1;
             }
 
-            last_generated_line = cloned_generated_line;
+            let line_mapping = line.mappings[cursor];
+            results[i] = Some(Mapping {
+                generated_line,
+                generated_column: line_mapping.generated_column,
+                original: line_mapping.original,
+            });
         }
-
-        Ok(())
+        results
     }
 
-    pub fn add_source(&mut self, source: &str) -> u32 {
-        let relative_source = make_relative_path(self.project_root.as_str(), source);
-        match self
-            .inner
-            .sources
-            .iter()
-            .position(|s| relative_source.eq(s))
-        {
-            Some(i) => i as u32,
-            None => {
-                self.inner.sources.push(relative_source);
-                (self.inner.sources.len() - 1) as u32
+    // Find the closest mapping and the generated and original range it spans, see MappingRange
+    pub fn mapping_ranges(
+        &mut self,
+        generated_line: u32,
+        generated_column: u32,
+    ) -> Option<MappingRange> {
+        let line = self.inner.mapping_lines.get_mut(generated_line as usize)?;
+        let line_mapping = line.find_closest_mapping(generated_column)?;
+        let next_index = line
+            .mappings
+            .partition_point(|m| m.generated_column <= line_mapping.generated_column);
+        let next_mapping = line.mappings.get(next_index);
+
+        let original_end = match (line_mapping.original, next_mapping.and_then(|m| m.original)) {
+            (Some(start), Some(end))
+                if start.source == end.source
+                    && (end.original_line, end.original_column)
+                        >= (start.original_line, start.original_column) =>
+            {
+                Some(end)
             }
-        }
-    }
+            _ => None,
+        };
 
-    pub fn add_sources(&mut self, sources: Vec<&str>) -> Vec<u32> {
-        self.inner.sources.reserve(sources.len());
-        let mut result_vec = Vec::with_capacity(sources.len());
-        for s in sources.iter() {
-            result_vec.push(self.add_source(s));
-        }
-        result_vec
+        Some(MappingRange {
+            generated_line,
+            generated_start_column: line_mapping.generated_column,
+            generated_end_column: next_mapping.map(|m| m.generated_column),
+            original_start: line_mapping.original,
+            original_end,
+        })
     }
 
-    pub fn get_source_index(&self, source: &str) -> Result<Option<u32>, SourceMapError> {
-        let normalized_source = make_relative_path(self.project_root.as_str(), source);
-        match self
+    // Same as find_closest_mapping, but a line after the last line with mappings returns the very last mapping
+    // of the sourcemap instead of None, e.g. for stack traces into code that was appended without mappings
+    pub fn find_closest_mapping_clamped(
+        &mut self,
+        generated_line: u32,
+        generated_column: u32,
+    ) -> Option<Mapping> {
+        let last_line = self
             .inner
-            .sources
+            .mapping_lines
             .iter()
-            .position(|s| normalized_source.eq(s))
-        {
-            Some(i) => Ok(Some(i as u32)),
-            None => Ok(None),
+            .rposition(|line| !line.mappings.is_empty())?;
+        if generated_line as usize > last_line {
+            return self.find_closest_mapping(last_line as u32, u32::MAX);
         }
-    }
 
-    pub fn get_source(&self, index: u32) -> Result<&str, SourceMapError> {
-        self.inner
-            .sources
-            .get(index as usize)
-            .map(|v| v.as_str())
-            .ok_or_else(|| SourceMapError::new(SourceMapErrorType::SourceOutOfRange))
+        self.find_closest_mapping(generated_line, generated_column)
     }
 
-    pub fn get_sources(&self) -> &Vec<String> {
-        &self.inner.sources
+    // Same as find_closest_mapping but walks back past mappings without an original position
+    pub fn find_closest_original_mapping(
+        &mut self,
+        generated_line: u32,
+        generated_column: u32,
+    ) -> Option<Mapping> {
+        let line = self.inner.mapping_lines.get_mut(generated_line as usize)?;
+        line.find_closest_original_mapping(generated_column)
+            .map(|line_mapping| Mapping {
+                generated_line,
+                generated_column: line_mapping.generated_column,
+                original: line_mapping.original,
+            })
     }
 
-    pub fn add_name(&mut self, name: &str) -> u32 {
-        return match self.inner.names.iter().position(|s| name.eq(s)) {
-            Some(i) => i as u32,
-            None => {
-                self.inner.names.push(String::from(name));
-                (self.inner.names.len() - 1) as u32
-            }
+    // Find the mapping at or before the generated position and the first mapping after it,
+    // if a line has no mapping on either side the search continues on the previous or next lines
+    pub fn surrounding_mappings(
+        &self,
+        generated_line: u32,
+        generated_column: u32,
+    ) -> (Option<Mapping>, Option<Mapping>) {
+        let line_index = generated_line as usize;
+        let to_mapping = |line: usize, mapping: &LineMapping| Mapping {
+            generated_line: line as u32,
+            generated_column: mapping.generated_column,
+            original: mapping.original,
         };
-    }
 
-    pub fn add_names(&mut self, names: Vec<&str>) -> Vec<u32> {
-        self.inner.names.reserve(names.len());
-        return names.iter().map(|n| self.add_name(n)).collect();
+        let mut previous = None;
+        for line in (0..=line_index.min(self.inner.mapping_lines.len())).rev() {
+            let mapping_line = match self.inner.mapping_lines.get(line) {
+                Some(mapping_line) => mapping_line,
+                None => continue,
+            };
+            let found = mapping_line
+                .mappings
+                .iter()
+                .filter(|m| line < line_index || m.generated_column <= generated_column)
+                .max_by_key(|m| m.generated_column);
+            if let Some(mapping) = found {
+                previous = Some(to_mapping(line, mapping));
+                break;
+            }
+        }
+
+        let mut next = None;
+        for (line, mapping_line) in self.inner.mapping_lines.iter().enumerate().skip(line_index) {
+            let found = mapping_line
+                .mappings
+                .iter()
+                .filter(|m| line > line_index || m.generated_column > generated_column)
+                .min_by_key(|m| m.generated_column);
+            if let Some(mapping) = found {
+                next = Some(to_mapping(line, mapping));
+                break;
+            }
+        }
+
+        (previous, next)
     }
 
-    pub fn get_name_index(&self, name: &str) -> Option<u32> {
+    // The last generated line that has mappings and the highest generated column on that line,
+    // (0, 0) if there are no mappings
+    pub fn generated_extent(&self) -> (u32, u32) {
         self.inner
-            .names
+            .mapping_lines
             .iter()
-            .position(|n| name.eq(n))
-            .map(|v| v as u32)
+            .enumerate()
+            .rev()
+            .find_map(|(generated_line, line)| {
+                line.mappings
+                    .iter()
+                    .map(|m| m.generated_column)
+                    .max()
+                    .map(|generated_column| (generated_line as u32, generated_column))
+            })
+            .unwrap_or((0, 0))
     }
 
-    pub fn get_name(&self, index: u32) -> Result<&str, SourceMapError> {
+    // Iterate over every generated line, including lines without mappings
+    pub fn lines(&self) -> impl Iterator<Item = (u32, &MappingLine)> {
         self.inner
-            .names
-            .get(index as usize)
-            .map(|v| v.as_str())
-            .ok_or_else(|| SourceMapError::new(SourceMapErrorType::NameOutOfRange))
+            .mapping_lines
+            .iter()
+            .enumerate()
+            .map(|(generated_line, line)| (generated_line as u32, line))
     }
 
-    pub fn get_names(&self) -> &Vec<String> {
-        &self.inner.names
+    // All mappings sorted by generated line and column, mappings within a line are only sorted lazily
+    // so the mappings of lines that haven't been sorted yet get sorted here
+    pub fn get_mappings(&self) -> Vec<Mapping> {
+        self.into_iter().collect()
     }
 
-    pub fn set_source_content(
-        &mut self,
-        source_index: usize,
-        source_content: &str,
-    ) -> Result<(), SourceMapError> {
-        if self.inner.sources.is_empty() || source_index > self.inner.sources.len() - 1 {
-            return Err(SourceMapError::new(SourceMapErrorType::SourceOutOfRange));
+    // Counts of sources, names, mappings, generated lines and sources with inlined content
+    pub fn stats(&self) -> SourceMapStats {
+        SourceMapStats {
+            sources: self.inner.sources.len(),
+            names: self.inner.names.len(),
+            mappings: self
+                .inner
+                .mapping_lines
+                .iter()
+                .map(|line| line.mappings.len())
+                .sum(),
+            lines: self.inner.mapping_lines.len(),
+            with_content: self
+                .inner
+                .sources_content
+                .iter()
+                .filter(|content| !content.is_empty())
+                .count(),
         }
+    }
 
-        let sources_content_len = self.inner.sources_content.len();
-        if sources_content_len > source_index {
-            self.inner.sources_content[source_index] = String::from(source_content);
-        } else {
-            self.inner
-                .sources_content
-                .reserve((source_index + 1) - sources_content_len);
-            let items_to_add = source_index - sources_content_len;
-            for _n in 0..items_to_add {
-                self.inner.sources_content.push(String::from(""));
+    // Verify that every mapping points to a position inside of the provided generated code,
+    // columns are counted in utf-16 code units like the rest of the sourcemap spec
+    pub fn verify_against_generated(&self, generated: &str) -> Result<(), SourceMapError> {
+        let generated_lines: Vec<&str> = generated.split('\n').collect();
+        for (generated_line, mapping_line) in self.inner.mapping_lines.iter().enumerate() {
+            if mapping_line.mappings.is_empty() {
+                continue;
+            }
+
+            let line_length = match generated_lines.get(generated_line) {
+                Some(line) => line.trim_end_matches('\r').encode_utf16().count(),
+                None => {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::MappingOutOfRange,
+                        &format!(
+                            "generated line {} is past the end of the generated code",
+                            generated_line
+                        ),
+                    ));
+                }
+            };
+
+            for mapping in mapping_line.mappings.iter() {
+                if mapping.generated_column as usize > line_length {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::MappingOutOfRange,
+                        &format!(
+                            "generated position {}:{} is past the end of the line",
+                            generated_line, mapping.generated_column
+                        ),
+                    ));
+                }
             }
-            self.inner
-                .sources_content
-                .push(String::from(source_content));
         }
 
         Ok(())
     }
 
-    pub fn get_source_content(&self, index: u32) -> Result<&str, SourceMapError> {
-        self.inner
-            .sources_content
-            .get(index as usize)
-            .map(|v| v.as_str())
-            .ok_or_else(|| SourceMapError::new(SourceMapErrorType::SourceOutOfRange))
-    }
+    // Verify that the last mapped generated line exists in the generated code,
+    // lines are counted the same way for both \n and \r\n line endings
+    pub fn verify_line_count(&self, generated: &str) -> Result<(), SourceMapError> {
+        let last_mapped_line = self
+            .inner
+            .mapping_lines
+            .iter()
+            .rposition(|line| !line.mappings.is_empty());
 
-    pub fn get_sources_content(&self) -> &Vec<String> {
-        &self.inner.sources_content
-    }
+        if let Some(last_mapped_line) = last_mapped_line {
+            let line_count = generated.lines().count();
+            if last_mapped_line >= line_count {
+                return Err(SourceMapError::new_with_reason(
+                    SourceMapErrorType::MappingOutOfRange,
+                    &format!(
+                        "mappings reach generated line {} but the generated code only has {} lines",
+                        last_mapped_line, line_count
+                    ),
+                ));
+            }
+        }
 
-    // Write the sourcemap instance to a buffer
-    pub fn to_buffer(&self, output: &mut AlignedVec) -> Result<(), SourceMapError> {
-        output.clear();
-        let mut serializer = AlignedSerializer::new(output);
-        serializer.serialize_value(&self.inner)?;
         Ok(())
     }
 
-    // Create a sourcemap instance from a buffer
-    pub fn from_buffer(project_root: &str, buf: &[u8]) -> Result<SourceMap, SourceMapError> {
-        let archived = unsafe { archived_root::<SourceMapInner>(buf) };
-        // TODO: see if we can use the archived data directly rather than deserializing at all...
-        let mut deserializer = AllocDeserializer;
-        let inner = archived.deserialize(&mut deserializer)?;
-        Ok(SourceMap {
-            project_root: String::from(project_root),
-            inner,
-        })
-    }
+    // Verify that every original position points inside of its source's content,
+    // sources without content can't be verified and are skipped
+    pub fn verify_original_positions(&self) -> Result<(), SourceMapError> {
+        let source_lines: Vec<Vec<&str>> = self
+            .inner
+            .sources_content
+            .iter()
+            .map(|content| {
+                if content.is_empty() {
+                    Vec::new()
+                } else {
+                    content.split('\n').collect()
+                }
+            })
+            .collect();
 
-    pub fn add_sourcemap(
-        &mut self,
-        sourcemap: &mut SourceMap,
-        line_offset: i64,
-    ) -> Result<(), SourceMapError> {
-        self.inner.sources.reserve(sourcemap.inner.sources.len());
-        let mut source_indexes = Vec::with_capacity(sourcemap.inner.sources.len());
-        let sources = std::mem::take(&mut sourcemap.inner.sources);
-        for s in sources.iter() {
-            source_indexes.push(self.add_source(s));
-        }
+        for mapping_line in self.inner.mapping_lines.iter() {
+            for mapping in mapping_line.mappings.iter() {
+                let original = match &mapping.original {
+                    Some(original) => original,
+                    None => continue,
+                };
 
-        self.inner.names.reserve(sourcemap.inner.names.len());
-        let mut names_indexes = Vec::with_capacity(sourcemap.inner.names.len());
-        let names = std::mem::take(&mut sourcemap.inner.names);
-        for n in names.iter() {
-            names_indexes.push(self.add_name(n));
-        }
+                let lines = match source_lines.get(original.source as usize) {
+                    Some(lines) if !lines.is_empty() => lines,
+                    _ => continue,
+                };
 
-        self.inner
-            .sources_content
-            .reserve(sourcemap.inner.sources_content.len());
-        let sources_content = std::mem::take(&mut sourcemap.inner.sources_content);
-        for (i, source_content_str) in sources_content.iter().enumerate() {
-            if let Some(source_index) = source_indexes.get(i) {
-                self.set_source_content(*source_index as usize, source_content_str)?;
+                let in_range = match lines.get(original.original_line as usize) {
+                    Some(line) => {
+                        original.original_column as usize
+                            <= line.trim_end_matches('\r').encode_utf16().count()
+                    }
+                    None => false,
+                };
+
+                if !in_range {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::MappingOutOfRange,
+                        &format!(
+                            "original position {}:{} is outside of source {}",
+                            original.original_line, original.original_column, original.source
+                        ),
+                    ));
+                }
             }
         }
 
-        let mapping_lines = std::mem::take(&mut sourcemap.inner.mapping_lines);
-        for (line, mapping_line) in mapping_lines.into_iter().enumerate() {
-            let generated_line = (line as i64) + line_offset;
-            if generated_line >= 0 {
-                let mut line = mapping_line;
-                for mapping in line.mappings.iter_mut() {
-                    match &mut mapping.original {
-                        Some(original_mapping_location) => {
-                            original_mapping_location.source = match source_indexes
-                                .get(original_mapping_location.source as usize)
-                            {
-                                Some(new_source_index) => *new_source_index,
-                                None => {
-                                    return Err(SourceMapError::new(
-                                        SourceMapErrorType::SourceOutOfRange,
-                                    ));
-                                }
-                            };
+        Ok(())
+    }
 
-                            original_mapping_location.name = match original_mapping_location.name {
-                                Some(name_index) => match names_indexes.get(name_index as usize) {
-                                    Some(new_name_index) => Some(*new_name_index),
-                                    None => {
-                                        return Err(SourceMapError::new(
-                                            SourceMapErrorType::NameOutOfRange,
-                                        ));
-                                    }
-                                },
-                                None => None,
-                            };
-                        }
-                        None => {}
-                    }
-                }
+    // Verify that the columns of every line are strictly increasing once the line is sorted, so no two mappings
+    // claim the same generated position and lines marked as sorted really are. Lines are stored by index so they
+    // are always in order. The vlq writer only keeps the last of duplicate columns, which hides these states.
+    pub fn validate(&self) -> Result<(), SourceMapError> {
+        for (generated_line, mapping_line) in self.inner.mapping_lines.iter().enumerate() {
+            let columns: Vec<u32> = if mapping_line.is_sorted {
+                mapping_line.columns().map(|m| m.generated_column).collect()
+            } else {
+                let mut columns: Vec<u32> =
+                    mapping_line.columns().map(|m| m.generated_column).collect();
+                columns.sort_unstable();
+                columns
+            };
 
-                self.ensure_lines(generated_line as usize);
-                self.inner.mapping_lines[generated_line as usize] = line;
+            for pair in columns.windows(2) {
+                if pair[1] <= pair[0] {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::InvalidMapping,
+                        &format!(
+                            "generated position {}:{} comes after column {}",
+                            generated_line, pair[1], pair[0]
+                        ),
+                    ));
+                }
             }
         }
 
         Ok(())
     }
 
-    pub fn extends(&mut self, original_sourcemap: &mut SourceMap) -> Result<(), SourceMapError> {
-        self.inner
-            .sources
-            .reserve(original_sourcemap.inner.sources.len());
-        let mut source_indexes = Vec::with_capacity(original_sourcemap.inner.sources.len());
-        for s in original_sourcemap.inner.sources.iter() {
-            source_indexes.push(self.add_source(s));
-        }
+    // Verify that no original column points into the middle of a surrogate pair of its source's content,
+    // the generated code isn't part of the sourcemap so only sources with content can be checked
+    pub fn validate_column_alignment(&self) -> Result<(), SourceMapError> {
+        let source_lines: Vec<Vec<&str>> = self
+            .inner
+            .sources_content
+            .iter()
+            .map(|content| {
+                if content.is_empty() {
+                    Vec::new()
+                } else {
+                    content.split('\n').collect()
+                }
+            })
+            .collect();
 
-        self.inner
-            .names
-            .reserve(original_sourcemap.inner.names.len());
-        let mut names_indexes = Vec::with_capacity(original_sourcemap.inner.names.len());
-        for n in original_sourcemap.inner.names.iter() {
-            names_indexes.push(self.add_name(n));
-        }
+        for mapping_line in self.inner.mapping_lines.iter() {
+            for mapping in mapping_line.mappings.iter() {
+                let original = match &mapping.original {
+                    Some(original) => original,
+                    None => continue,
+                };
 
-        self.inner
-            .sources_content
-            .reserve(original_sourcemap.inner.sources_content.len());
-        for (i, source_content_str) in original_sourcemap.inner.sources_content.iter().enumerate() {
-            if let Some(source_index) = source_indexes.get(i) {
-                self.set_source_content(*source_index as usize, source_content_str)?;
-            }
-        }
+                let line = match source_lines
+                    .get(original.source as usize)
+                    .and_then(|lines| lines.get(original.original_line as usize))
+                {
+                    Some(line) => line,
+                    None => continue,
+                };
 
-        for (_generated_line, line_content) in self.inner.mapping_lines.iter_mut().enumerate() {
-            for mapping in line_content.mappings.iter_mut() {
-                let original_location_option = &mut mapping.original;
-                if let Some(original_location) = original_location_option {
-                    let found_mapping = original_sourcemap.find_closest_mapping(
-                        original_location.original_line,
-                        original_location.original_column,
-                    );
-                    match found_mapping {
-                        Some(original_mapping) => match original_mapping.original {
-                            Some(original_mapping_location) => {
-                                *original_location_option = Some(OriginalLocation::new(
-                                    original_mapping_location.original_line,
-                                    original_mapping_location.original_column,
-                                    match source_indexes
-                                        .get(original_mapping_location.source as usize)
-                                    {
-                                        Some(new_source_index) => *new_source_index,
-                                        None => {
-                                            return Err(SourceMapError::new(
-                                                SourceMapErrorType::SourceOutOfRange,
-                                            ));
-                                        }
-                                    },
-                                    match original_mapping_location.name {
-                                        Some(name_index) => {
-                                            match names_indexes.get(name_index as usize) {
-                                                Some(new_name_index) => Some(*new_name_index),
-                                                None => {
-                                                    return Err(SourceMapError::new(
-                                                        SourceMapErrorType::NameOutOfRange,
-                                                    ));
-                                                }
-                                            }
-                                        }
-                                        None => None,
-                                    },
-                                ));
-                            }
-                            None => {
-                                *original_location_option = None;
-                            }
-                        },
-                        None => {
-                            *original_location_option = None;
-                        }
+                let mut code_unit = 0;
+                for c in line.chars() {
+                    if code_unit >= original.original_column as usize {
+                        break;
+                    }
+
+                    if c.len_utf16() == 2 && code_unit + 1 == original.original_column as usize {
+                        return Err(SourceMapError::new_with_reason(
+                            SourceMapErrorType::InvalidMapping,
+                            &format!(
+                                "original position {}:{} in source {} is in the middle of a surrogate pair",
+                                original.original_line, original.original_column, original.source
+                            ),
+                        ));
                     }
+                    code_unit += c.len_utf16();
                 }
             }
         }
@@ -508,179 +930,4489 @@ impl SourceMap {
         Ok(())
     }
 
-    pub fn add_vlq_map(
-        &mut self,
-        input: &[u8],
-        sources: Vec<&str>,
-        sources_content: Vec<&str>,
-        names: Vec<&str>,
-        line_offset: i64,
-        column_offset: i64,
-    ) -> Result<(), SourceMapError> {
-        let mut generated_line: i64 = line_offset;
-        let mut generated_column: i64 = column_offset;
-        let mut original_line = 0;
-        let mut original_column = 0;
-        let mut source = 0;
-        let mut name = 0;
-
-        let source_indexes: Vec<u32> = self.add_sources(sources);
-        let name_indexes: Vec<u32> = self.add_names(names);
+    // Serialize the mappings, parse them into a new sourcemap and check nothing changed,
+    // the error reason contains the first mapping that differs
+    pub fn assert_roundtrip(&mut self) -> Result<(), SourceMapError> {
+        let mut vlq_output: Vec<u8> = vec![];
+        self.write_vlq(&mut vlq_output)?;
 
-        self.inner.sources_content.reserve(sources_content.len());
-        for (i, source_content) in sources_content.iter().enumerate() {
-            self.set_source_content(i, source_content)?;
-        }
+        let mut parsed = SourceMap::new(self.project_root.as_str());
+        parsed.add_vlq_map(
+            &vlq_output,
+            self.inner.sources.iter().map(|s| s.as_str()).collect(),
+            self.inner
+                .sources_content
+                .iter()
+                .map(|s| s.as_str())
+                .collect(),
+            self.inner.names.iter().map(|s| s.as_str()).collect(),
+            0,
+            0,
+        )?;
 
-        let mut input = input.iter().cloned().peekable();
-        while let Some(byte) = input.peek().cloned() {
-            match byte {
-                b';' => {
-                    generated_line += 1;
-                    generated_column = column_offset;
-                    input.next().unwrap();
-                }
-                b',' => {
-                    input.next().unwrap();
+        let mut expected = self.into_iter();
+        let mut actual = parsed.into_iter();
+        loop {
+            match (expected.next(), actual.next()) {
+                (None, None) => return Ok(()),
+                (Some(expected), Some(actual)) if expected == actual => {}
+                (Some(expected), actual) => {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::InvalidMapping,
+                        format!(
+                            "mapping at {}:{} is {:?} after roundtrip, expected {:?}",
+                            expected.generated_line,
+                            expected.generated_column,
+                            actual.and_then(|m| m.original),
+                            expected.original
+                        )
+                        .as_str(),
+                    ));
                 }
-                _ => {
-                    // First is a generated column that is always present.
-                    read_relative_vlq(&mut generated_column, &mut input)?;
-
-                    // Read source, original line, and original column if the
-                    // mapping has them.
-                    let original = if input.peek().cloned().map_or(true, is_mapping_separator) {
-                        None
-                    } else {
-                        read_relative_vlq(&mut source, &mut input)?;
-                        read_relative_vlq(&mut original_line, &mut input)?;
-                        read_relative_vlq(&mut original_column, &mut input)?;
-                        Some(OriginalLocation::new(
-                            original_line as u32,
-                            original_column as u32,
-                            match source_indexes.get(source as usize) {
-                                Some(v) => *v,
-                                None => {
-                                    return Err(SourceMapError::new(
-                                        SourceMapErrorType::SourceOutOfRange,
-                                    ));
-                                }
-                            },
-                            if input.peek().cloned().map_or(true, is_mapping_separator) {
-                                None
-                            } else {
-                                read_relative_vlq(&mut name, &mut input)?;
-                                Some(match name_indexes.get(name as usize) {
-                                    Some(v) => *v,
-                                    None => {
-                                        return Err(SourceMapError::new(
-                                            SourceMapErrorType::NameOutOfRange,
-                                        ));
-                                    }
-                                })
-                            },
-                        ))
-                    };
-
-                    if generated_line >= 0 {
-                        self.add_mapping(generated_line as u32, generated_column as u32, original);
-                    }
+                (None, Some(actual)) => {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::InvalidMapping,
+                        format!(
+                            "unexpected mapping at {}:{} after roundtrip",
+                            actual.generated_line, actual.generated_column
+                        )
+                        .as_str(),
+                    ));
                 }
             }
         }
-
-        Ok(())
     }
 
-    pub fn offset_columns(
-        &mut self,
-        generated_line: u32,
-        generated_column: u32,
-        generated_column_offset: i64,
-    ) -> Result<(), SourceMapError> {
-        match self.inner.mapping_lines.get_mut(generated_line as usize) {
-            Some(line) => line.offset_columns(generated_column, generated_column_offset),
-            None => Ok(()),
+    // Advisory checks for sourcemaps that are valid but most likely not what was intended
+    pub fn warnings(&self) -> Vec<SourceMapError> {
+        let mut warnings = Vec::new();
+        if !self.inner.sources.is_empty()
+            && self
+                .inner
+                .mapping_lines
+                .iter()
+                .all(|line| line.mappings.is_empty())
+        {
+            warnings.push(SourceMapError::new(SourceMapErrorType::NoMappings));
         }
+        warnings
     }
 
-    pub fn offset_lines(
-        &mut self,
-        generated_line: u32,
-        generated_line_offset: i64,
-    ) -> Result<(), SourceMapError> {
-        if generated_line_offset == 0 || self.inner.mapping_lines.is_empty() {
-            return Ok(());
-        }
+    // Amount of mappings for each generated line that has mappings, useful to spot lines with an unusual amount of mappings
+    pub fn line_density_histogram(&self) -> BTreeMap<u32, usize> {
+        self.inner
+            .mapping_lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.mappings.is_empty())
+            .map(|(generated_line, line)| (generated_line as u32, line.mappings.len()))
+            .collect()
+    }
 
-        let (start_line, overflowed) =
-            (generated_line as i64).overflowing_add(generated_line_offset);
-        if overflowed || start_line > (u32::MAX as i64) {
-            return Err(SourceMapError::new_with_reason(
-                SourceMapErrorType::UnexpectedNegativeNumber,
-                "column + column_offset cannot be negative",
-            ));
-        }
+    // The top_n generated lines with the most mappings, most mappings first and ties ordered by line.
+    // These are the lines that make offset_columns and find_closest_mapping slow.
+    pub fn densest_lines(&self, top_n: usize) -> Vec<(u32, usize)> {
+        let mut lines: Vec<(u32, usize)> = self.line_density_histogram().into_iter().collect();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        lines.truncate(top_n);
+        lines
+    }
 
-        let line = generated_line as usize;
-        let abs_offset = generated_line_offset.abs() as usize;
-        if generated_line_offset > 0 {
-            if line > self.inner.mapping_lines.len() {
-                self.ensure_lines(line + abs_offset);
-            } else {
-                self.inner
-                    .mapping_lines
-                    .splice(line..line, (0..abs_offset).map(|_| MappingLine::new()));
-            }
-        } else {
-            self.inner.mapping_lines.drain(line - abs_offset..line);
-        }
+    // Highest original line mapped for a source, a cheap estimate of the source's line count when
+    // there is no sources content. None if no mapping points into the source.
+    pub fn max_original_line(&self, source_index: u32) -> Option<u32> {
+        self.inner
+            .mapping_lines
+            .iter()
+            .flat_map(|line| line.mappings.iter())
+            .filter_map(|mapping| mapping.original)
+            .filter(|original| original.source == source_index)
+            .map(|original| original.original_line)
+            .max()
+    }
 
-        Ok(())
+    pub fn write_vlq<W>(&mut self, output: &mut W) -> Result<(), SourceMapError>
+    where
+        W: io::Write,
+    {
+        self.write_vlq_from(output, 0)
     }
 
-    pub fn add_empty_map(
+    // Write the mappings starting at base_line, base_line is written as if it was the first line
+    pub fn write_vlq_from<W>(
         &mut self,
-        source: &str,
-        source_content: &str,
-        line_offset: i64,
-    ) -> Result<(), SourceMapError> {
-        let source_index = self.add_source(source);
-        self.set_source_content(source_index as usize, source_content)?;
+        output: &mut W,
+        base_line: u32,
+    ) -> Result<(), SourceMapError>
+    where
+        W: io::Write,
+    {
+        let mut writer = VlqWriter::with_base_line(base_line);
+        for (generated_line, line_content) in self
+            .inner
+            .mapping_lines
+            .iter_mut()
+            .enumerate()
+            .skip(base_line as usize)
+        {
+            line_content.ensure_sorted();
+            writer.write_line(generated_line as u32, line_content, output)?;
+        }
 
-        for (line_count, _line) in source_content.lines().enumerate() {
-            let generated_line = (line_count as i64) + line_offset;
-            if generated_line >= 0 {
-                self.add_mapping(
+        Ok(())
+    }
+
+    pub fn add_source(&mut self, source: &str) -> u32 {
+        let relative_source = make_relative_path(self.project_root.as_str(), source);
+        match self
+            .inner
+            .sources
+            .iter()
+            .position(|s| relative_source.eq(s))
+        {
+            Some(i) => i as u32,
+            None => {
+                self.inner.sources.push(relative_source);
+                (self.inner.sources.len() - 1) as u32
+            }
+        }
+    }
+
+    // Insert a source at index 0, every existing source index in the mappings shifts up by one.
+    // A source that already exists is moved to index 0 instead, then only the sources before it shift up.
+    // source_content replaces the content of the source, None keeps the content a moved source already has.
+    pub fn prepend_source(&mut self, source: &str, source_content: Option<&str>) -> u32 {
+        let relative_source = make_relative_path(self.project_root.as_str(), source);
+        let index = match self
+            .inner
+            .sources
+            .iter()
+            .position(|s| relative_source.eq(s))
+        {
+            Some(i) => i,
+            None => {
+                self.inner.sources.push(relative_source);
+                self.inner.sources.len() - 1
+            }
+        };
+        self.inner.sources[..=index].rotate_right(1);
+
+        if self.inner.sources_content.len() > index {
+            self.inner.sources_content[..=index].rotate_right(1);
+        } else if !self.inner.sources_content.is_empty() || source_content.is_some() {
+            self.inner.sources_content.insert(0, String::new());
+        }
+        if let Some(source_content) = source_content {
+            self.inner.sources_content[0] = String::from(source_content);
+        }
+
+        let index = index as u32;
+        for mapping_line in self.inner.mapping_lines.iter_mut() {
+            for mapping in mapping_line.mappings.iter_mut() {
+                if let Some(original) = &mut mapping.original {
+                    if original.source == index {
+                        original.source = 0;
+                    } else if original.source < index {
+                        original.source += 1;
+                    }
+                }
+            }
+        }
+
+        0
+    }
+
+    pub fn add_sources(&mut self, sources: Vec<&str>) -> Vec<u32> {
+        self.inner.sources.reserve(sources.len());
+        let mut result_vec = Vec::with_capacity(sources.len());
+        for s in sources.iter() {
+            result_vec.push(self.add_source(s));
+        }
+        result_vec
+    }
+
+    pub fn get_source_index(&self, source: &str) -> Result<Option<u32>, SourceMapError> {
+        let normalized_source = make_relative_path(self.project_root.as_str(), source);
+        match self
+            .inner
+            .sources
+            .iter()
+            .position(|s| normalized_source.eq(s))
+        {
+            Some(i) => Ok(Some(i as u32)),
+            None => Ok(None),
+        }
+    }
+
+    // Check if a source exists without adding it, the source is made relative to the project root first like in add_source
+    pub fn contains_source(&self, source: &str) -> bool {
+        matches!(self.get_source_index(source), Ok(Some(_)))
+    }
+
+    pub fn get_source(&self, index: u32) -> Result<&str, SourceMapError> {
+        self.inner
+            .sources
+            .get(index as usize)
+            .map(|v| v.as_str())
+            .ok_or_else(|| SourceMapError::new(SourceMapErrorType::SourceOutOfRange))
+    }
+
+    pub fn get_sources(&self) -> &Vec<String> {
+        &self.inner.sources
+    }
+
+    // Iterate over the sources together with their index
+    pub fn enumerate_sources(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.inner
+            .sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| (i as u32, source.as_str()))
+    }
+
+    // Point every mapping to a new source index, source_indexes is indexed by the current source index.
+    // Mappings for which the new index is None lose their original location.
+    fn remap_sources(&mut self, source_indexes: &[Option<u32>]) -> Result<(), SourceMapError> {
+        for mapping_line in self.inner.mapping_lines.iter() {
+            for mapping in mapping_line.mappings.iter() {
+                if let Some(original) = &mapping.original {
+                    if original.source as usize >= source_indexes.len() {
+                        return Err(SourceMapError::new(SourceMapErrorType::SourceOutOfRange));
+                    }
+                }
+            }
+        }
+
+        for mapping_line in self.inner.mapping_lines.iter_mut() {
+            for mapping in mapping_line.mappings.iter_mut() {
+                if let Some(original) = &mut mapping.original {
+                    match source_indexes[original.source as usize] {
+                        Some(source_index) => original.source = source_index,
+                        None => mapping.original = None,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Remove every source for which keep returns false together with its content,
+    // mappings pointing into a removed source lose their original location
+    pub fn retain_sources<F>(&mut self, keep: F) -> Result<(), SourceMapError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut source_indexes: Vec<Option<u32>> = Vec::with_capacity(self.inner.sources.len());
+        let mut sources: Vec<String> = Vec::with_capacity(self.inner.sources.len());
+        let mut sources_content: Vec<String> = Vec::with_capacity(self.inner.sources_content.len());
+        for (i, source) in self.inner.sources.iter().enumerate() {
+            if !keep(source) {
+                source_indexes.push(None);
+                continue;
+            }
+
+            source_indexes.push(Some(sources.len() as u32));
+            sources.push(source.clone());
+            if let Some(content) = self.inner.sources_content.get(i) {
+                sources_content.push(content.clone());
+            }
+        }
+
+        if sources.len() == self.inner.sources.len() {
+            return Ok(());
+        }
+
+        self.remap_sources(&source_indexes)?;
+        while matches!(sources_content.last(), Some(content) if content.is_empty()) {
+            sources_content.pop();
+        }
+        self.inner.sources = sources;
+        self.inner.sources_content = sources_content;
+        Ok(())
+    }
+
+    // Fix mappings that point to a source or name that doesn't exist, so the sourcemap is safe to serialize.
+    // A mapping with an unknown source loses its original location, a mapping with an unknown name only loses the name.
+    // Returns the amount of mappings that got fixed.
+    pub fn remove_dangling_mappings(&mut self) -> usize {
+        let sources_len = self.inner.sources.len();
+        let names_len = self.inner.names.len();
+        let mut fixed = 0;
+        for mapping_line in self.inner.mapping_lines.iter_mut() {
+            for mapping in mapping_line.mappings.iter_mut() {
+                if let Some(original) = &mut mapping.original {
+                    if original.source as usize >= sources_len {
+                        mapping.original = None;
+                        fixed += 1;
+                    } else if matches!(original.name, Some(name) if name as usize >= names_len) {
+                        original.name = None;
+                        fixed += 1;
+                    }
+                }
+            }
+        }
+        fixed
+    }
+
+    // Collapse sources with the same path into the first occurrence and update all mappings,
+    // when duplicates have different content the first non-empty content is kept
+    pub fn dedupe_sources(&mut self) -> Result<(), SourceMapError> {
+        let mut source_indexes: Vec<Option<u32>> = Vec::with_capacity(self.inner.sources.len());
+        let mut sources: Vec<String> = Vec::with_capacity(self.inner.sources.len());
+        let mut sources_content: Vec<String> = Vec::with_capacity(self.inner.sources_content.len());
+        let mut seen: HashMap<&str, u32> = HashMap::new();
+        for (i, source) in self.inner.sources.iter().enumerate() {
+            let content = self
+                .inner
+                .sources_content
+                .get(i)
+                .map(|c| c.as_str())
+                .unwrap_or("");
+            match seen.get(source.as_str()) {
+                Some(source_index) => {
+                    source_indexes.push(Some(*source_index));
+                    let source_index = *source_index as usize;
+                    if !content.is_empty() && sources_content[source_index].is_empty() {
+                        sources_content[source_index] = String::from(content);
+                    }
+                }
+                None => {
+                    let source_index = sources.len() as u32;
+                    seen.insert(source.as_str(), source_index);
+                    source_indexes.push(Some(source_index));
+                    sources.push(source.clone());
+                    sources_content.push(String::from(content));
+                }
+            }
+        }
+
+        if sources.len() == self.inner.sources.len() {
+            return Ok(());
+        }
+
+        self.remap_sources(&source_indexes)?;
+        while matches!(sources_content.last(), Some(content) if content.is_empty()) {
+            sources_content.pop();
+        }
+        self.inner.sources = sources;
+        self.inner.sources_content = sources_content;
+        Ok(())
+    }
+
+    // Replace from_prefix with to_prefix in every source path that starts with it and return the amount of rewritten sources,
+    // sources that end up with the same path are merged like in dedupe_sources
+    pub fn rewrite_source_prefix(
+        &mut self,
+        from_prefix: &str,
+        to_prefix: &str,
+    ) -> Result<usize, SourceMapError> {
+        let mut rewritten = 0;
+        for source in self.inner.sources.iter_mut() {
+            if let Some(rest) = source.strip_prefix(from_prefix) {
+                *source = format!("{}{}", to_prefix, rest);
+                rewritten += 1;
+            }
+        }
+
+        if rewritten > 0 {
+            self.dedupe_sources()?;
+        }
+        Ok(rewritten)
+    }
+
+    // Collapse the `.` and `..` segments of every source path, see utils::normalize_path,
+    // sources that end up with the same path are merged like in dedupe_sources
+    pub fn canonicalize_source_paths(&mut self) -> Result<(), SourceMapError> {
+        for source in self.inner.sources.iter_mut() {
+            *source = normalize_path(source);
+        }
+
+        self.dedupe_sources()
+    }
+
+    // Change the project root, with rebase the relative sources are resolved against the old root and made
+    // relative to the new one so they keep pointing to the same files, otherwise the sources are left as is.
+    // Sources that end up with the same path are merged like in dedupe_sources.
+    pub fn set_project_root(&mut self, new_root: &str, rebase: bool) -> Result<(), SourceMapError> {
+        if rebase {
+            if !is_abs_path(self.project_root.as_str()) || !is_abs_path(new_root) {
+                return Err(SourceMapError::new_with_reason(
+                    SourceMapErrorType::InvalidFilePath,
+                    "project_root has to be absolute to rebase sources",
+                ));
+            }
+
+            for source in self.inner.sources.iter_mut() {
+                let absolute_source = make_absolute_path(self.project_root.as_str(), source);
+                *source = make_relative_path(new_root, absolute_source.as_str());
+            }
+        }
+
+        self.project_root = String::from(new_root);
+        if rebase {
+            self.dedupe_sources()?;
+        }
+        Ok(())
+    }
+
+    // Resolve all relative sources against the project root, absolute paths and urls are left untouched
+    pub fn absolutize_sources(&mut self) -> Result<(), SourceMapError> {
+        if !is_abs_path(self.project_root.as_str()) {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::InvalidFilePath,
+                "project_root has to be absolute",
+            ));
+        }
+
+        for source in self.inner.sources.iter_mut() {
+            *source = make_absolute_path(self.project_root.as_str(), source);
+        }
+
+        Ok(())
+    }
+
+    // Get all sources as file:// urls, relative sources are resolved against the project root first, other urls are left untouched
+    pub fn sources_as_file_urls(&self) -> Vec<String> {
+        self.inner
+            .sources
+            .iter()
+            .map(|source| {
+                let absolute_source = make_absolute_path(self.project_root.as_str(), source);
+                if is_abs_path(absolute_source.as_str()) {
+                    file_url_from_path(absolute_source.as_str())
+                } else {
+                    absolute_source
+                }
+            })
+            .collect()
+    }
+
+    pub fn add_name(&mut self, name: &str) -> u32 {
+        if self.name_dedup == NameDedup::None {
+            self.inner.names.push(String::from(name));
+            return (self.inner.names.len() - 1) as u32;
+        }
+
+        return match self.inner.names.iter().position(|s| name.eq(s)) {
+            Some(i) => i as u32,
+            None => {
+                self.inner.names.push(String::from(name));
+                (self.inner.names.len() - 1) as u32
+            }
+        };
+    }
+
+    // Add all names of another sourcemap, returns the new index of each name.
+    // Names are deduplicated against the existing names and against each other, unless name_dedup is None,
+    // then the names are appended as is and every index just gets offset by the amount of existing names.
+    fn append_names(&mut self, names: Vec<String>) -> Vec<u32> {
+        if self.name_dedup == NameDedup::None {
+            let names_offset = self.inner.names.len() as u32;
+            let names_indexes = (names_offset..names_offset + names.len() as u32).collect();
+            self.inner.names.extend(names);
+            return names_indexes;
+        }
+
+        append_deduplicated(&mut self.inner.names, names)
+    }
+
+    // Deduplicate sources and names in a single pass, meant to run once after merging many sourcemaps
+    // without deduplication (e.g. with NameDedup::None) instead of deduplicating on every merge
+    pub fn coalesce(&mut self) -> Result<(), SourceMapError> {
+        self.dedupe_sources()?;
+
+        let mut name_indexes: Vec<u32> = Vec::with_capacity(self.inner.names.len());
+        let mut names: Vec<String> = Vec::with_capacity(self.inner.names.len());
+        let mut seen: HashMap<&str, u32> = HashMap::new();
+        for name in self.inner.names.iter() {
+            let name_index = *seen.entry(name.as_str()).or_insert_with(|| {
+                names.push(name.clone());
+                (names.len() - 1) as u32
+            });
+            name_indexes.push(name_index);
+        }
+
+        if names.len() == self.inner.names.len() {
+            return Ok(());
+        }
+
+        for mapping_line in self.inner.mapping_lines.iter() {
+            for mapping in mapping_line.mappings.iter() {
+                let name = mapping.original.and_then(|original| original.name);
+                if matches!(name, Some(name) if name as usize >= name_indexes.len()) {
+                    return Err(SourceMapError::new(SourceMapErrorType::NameOutOfRange));
+                }
+            }
+        }
+
+        for mapping_line in self.inner.mapping_lines.iter_mut() {
+            for mapping in mapping_line.mappings.iter_mut() {
+                if let Some(OriginalLocation {
+                    name: Some(name), ..
+                }) = &mut mapping.original
+                {
+                    *name = name_indexes[*name as usize];
+                }
+            }
+        }
+        self.inner.names = names;
+        Ok(())
+    }
+
+    pub fn add_names(&mut self, names: Vec<&str>) -> Vec<u32> {
+        self.inner.names.reserve(names.len());
+        return names.iter().map(|n| self.add_name(n)).collect();
+    }
+
+    pub fn get_name_index(&self, name: &str) -> Option<u32> {
+        self.inner
+            .names
+            .iter()
+            .position(|n| name.eq(n))
+            .map(|v| v as u32)
+    }
+
+    pub fn contains_name(&self, name: &str) -> bool {
+        self.get_name_index(name).is_some()
+    }
+
+    pub fn get_name(&self, index: u32) -> Result<&str, SourceMapError> {
+        self.inner
+            .names
+            .get(index as usize)
+            .map(|v| v.as_str())
+            .ok_or_else(|| SourceMapError::new(SourceMapErrorType::NameOutOfRange))
+    }
+
+    pub fn get_names(&self) -> &Vec<String> {
+        &self.inner.names
+    }
+
+    // How many mappings reference each name, names that no mapping uses are included with a count of 0
+    pub fn name_usage_counts(&self) -> HashMap<u32, usize> {
+        let mut counts: HashMap<u32, usize> = (0..self.inner.names.len() as u32)
+            .map(|name| (name, 0))
+            .collect();
+        for mapping_line in self.inner.mapping_lines.iter() {
+            for mapping in mapping_line.mappings.iter() {
+                if let Some(name) = mapping.original.and_then(|original| original.name) {
+                    *counts.entry(name).or_default() += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    // Iterate over the names together with their index
+    pub fn enumerate_names(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.inner
+            .names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (i as u32, name.as_str()))
+    }
+
+    pub fn sources_capacity(&self) -> usize {
+        self.inner.sources.capacity()
+    }
+
+    pub fn names_capacity(&self) -> usize {
+        self.inner.names.capacity()
+    }
+
+    // Remove all sources, names and mappings but keep the allocations around so the sourcemap can be reused
+    pub fn clear(&mut self) {
+        self.inner.sources.clear();
+        self.inner.sources_content.clear();
+        self.inner.names.clear();
+        self.inner.mapping_lines.clear();
+        self.inner.debug_id = None;
+    }
+
+    // Copy this sourcemap into another one, reusing the allocations of the destination where possible
+    pub fn clone_into(&self, dest: &mut SourceMap) {
+        dest.project_root.clone_from(&self.project_root);
+        dest.name_dedup = self.name_dedup;
+        dest.inner.sources.clone_from(&self.inner.sources);
+        dest.inner
+            .sources_content
+            .clone_from(&self.inner.sources_content);
+        dest.inner.names.clone_from(&self.inner.names);
+        dest.inner
+            .mapping_lines
+            .clone_from(&self.inner.mapping_lines);
+        dest.inner.debug_id.clone_from(&self.inner.debug_id);
+        dest.content_loader.clone_from(&self.content_loader);
+    }
+
+    // Run a sequence of edits atomically, when f returns an error the sourcemap is restored
+    // to the state it was in before f ran and the error is returned
+    pub fn transaction<F>(&mut self, f: F) -> Result<(), SourceMapError>
+    where
+        F: FnOnce(&mut SourceMap) -> Result<(), SourceMapError>,
+    {
+        let mut snapshot = SourceMap::new("");
+        self.clone_into(&mut snapshot);
+        if let Err(err) = f(self) {
+            *self = snapshot;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // The debug id (usually a UUID) that links generated code to its sourcemap, stored as is
+    pub fn get_debug_id(&self) -> Option<&str> {
+        self.inner.debug_id.as_deref()
+    }
+
+    pub fn set_debug_id(&mut self, debug_id: Option<&str>) {
+        self.inner.debug_id = debug_id.map(String::from);
+    }
+
+    pub fn set_source_content(
+        &mut self,
+        source_index: usize,
+        source_content: &str,
+    ) -> Result<(), SourceMapError> {
+        if self.inner.sources.is_empty() || source_index > self.inner.sources.len() - 1 {
+            return Err(SourceMapError::new(SourceMapErrorType::SourceOutOfRange));
+        }
+
+        let sources_content_len = self.inner.sources_content.len();
+        if sources_content_len > source_index {
+            self.inner.sources_content[source_index] = String::from(source_content);
+        } else {
+            self.inner
+                .sources_content
+                .reserve((source_index + 1) - sources_content_len);
+            let items_to_add = source_index - sources_content_len;
+            for _n in 0..items_to_add {
+                self.inner.sources_content.push(String::from(""));
+            }
+            self.inner
+                .sources_content
+                .push(String::from(source_content));
+        }
+
+        Ok(())
+    }
+
+    // Set the content of many sources at once, contents[i] is the content of source i and None leaves it as is
+    pub fn set_sources_content(
+        &mut self,
+        contents: Vec<Option<String>>,
+    ) -> Result<(), SourceMapError> {
+        if contents.len() > self.inner.sources.len() {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::SourceOutOfRange,
+                "there are more contents than sources",
+            ));
+        }
+
+        for (source_index, content) in contents.into_iter().enumerate() {
+            if let Some(content) = content {
+                if source_index < self.inner.sources_content.len() {
+                    self.inner.sources_content[source_index] = content;
+                } else {
+                    self.inner
+                        .sources_content
+                        .resize(source_index, String::new());
+                    self.inner.sources_content.push(content);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_source_content(&self, index: u32) -> Result<&str, SourceMapError> {
+        self.inner
+            .sources_content
+            .get(index as usize)
+            .map(|v| v.as_str())
+            .ok_or_else(|| SourceMapError::new(SourceMapErrorType::SourceOutOfRange))
+    }
+
+    // Register a loader that load_source_content calls for sources without embedded content
+    pub fn set_content_loader<F>(&mut self, loader: F)
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.content_loader = Some(ContentLoader(Arc::new(loader)));
+    }
+
+    // Like get_source_content, but when the source has no content yet the content loader is asked for it
+    // and whatever it returns is stored in sources_content, so the loader runs at most once per source.
+    // A source without content that the loader can't provide either has an empty content.
+    pub fn load_source_content(&mut self, index: u32) -> Result<&str, SourceMapError> {
+        let source = self.get_source(index)?;
+        let has_content = matches!(self.inner.sources_content.get(index as usize), Some(content) if !content.is_empty());
+        if !has_content {
+            if let Some(ContentLoader(loader)) = &self.content_loader {
+                if let Some(content) = loader(source) {
+                    self.set_source_content(index as usize, &content)?;
+                }
+            }
+        }
+
+        Ok(self
+            .inner
+            .sources_content
+            .get(index as usize)
+            .map(|v| v.as_str())
+            .unwrap_or(""))
+    }
+
+    // Get the inlined content of a source by its path, None if the source doesn't exist or has no content
+    pub fn source_content_for_path(&self, source: &str) -> Option<&str> {
+        let source_index = self.get_source_index(source).ok()??;
+        match self.get_source_content(source_index) {
+            Ok(content) if !content.is_empty() => Some(content),
+            _ => None,
+        }
+    }
+
+    pub fn get_sources_content(&self) -> &Vec<String> {
+        &self.inner.sources_content
+    }
+
+    // Convert \r\n line endings in all sources content to \n.
+    // This assumes the original positions of the mappings were computed on the \n version of the sources,
+    // if they were computed on the \r\n version the content no longer matches them afterwards.
+    pub fn normalize_content_line_endings(&mut self) {
+        for content in self.inner.sources_content.iter_mut() {
+            if content.contains("\r\n") {
+                *content = content.replace("\r\n", "\n");
+            }
+        }
+    }
+
+    // Total size of all inlined sources content in bytes
+    pub fn sources_content_byte_size(&self) -> usize {
+        self.inner.sources_content.iter().map(|c| c.len()).sum()
+    }
+
+    // Indexes of the sources whose content differs from the content of the source with the same path in previous,
+    // sources that don't exist in previous count as changed. Paths are compared as stored, relative to the project root.
+    pub fn content_diff(&self, previous: &SourceMap) -> Vec<u32> {
+        let previous_content: HashMap<&str, &str> = previous
+            .inner
+            .sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                let content = previous
+                    .inner
+                    .sources_content
+                    .get(i)
+                    .map(|c| c.as_str())
+                    .unwrap_or("");
+                (source.as_str(), content)
+            })
+            .collect();
+
+        self.enumerate_sources()
+            .filter(|(i, source)| {
+                let content = self
+                    .inner
+                    .sources_content
+                    .get(*i as usize)
+                    .map(|c| c.as_str())
+                    .unwrap_or("");
+                previous_content.get(source) != Some(&content)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // Write the sourcemap instance to a buffer
+    pub fn to_buffer(&self, output: &mut AlignedVec) -> Result<(), SourceMapError> {
+        output.clear();
+        output.extend_from_slice(BUFFER_MAGIC);
+        output.extend_from_slice(&BUFFER_VERSION.to_le_bytes());
+        output.extend_from_slice(&[0; BUFFER_HEADER_SIZE - 8]);
+        let mut serializer = AlignedSerializer::new(output);
+        serializer.serialize_value(&self.inner)?;
+        Ok(())
+    }
+
+    // Create a sourcemap instance from a buffer
+    pub fn from_buffer(project_root: &str, buf: &[u8]) -> Result<SourceMap, SourceMapError> {
+        let archived = archived_buffer(buf)?;
+        // TODO: see if we can use the archived data directly rather than deserializing at all...
+        let mut deserializer = AllocDeserializer;
+        let inner = SourceMapInner {
+            sources: deserialize_strings(&archived.sources),
+            sources_content: deserialize_strings(&archived.sources_content),
+            names: deserialize_strings(&archived.names),
+            mapping_lines: archived.mapping_lines.deserialize(&mut deserializer)?,
+            debug_id: archived.debug_id.as_ref().map(|s| String::from(s.as_str())),
+        };
+        Ok(SourceMap {
+            project_root: String::from(project_root),
+            name_dedup: NameDedup::default(),
+            inner,
+            content_loader: None,
+        })
+    }
+
+    // Take ownership of the sources, sources content, names and mapping lines without cloning them
+    pub fn into_parts(self) -> (Vec<String>, Vec<String>, Vec<String>, Vec<MappingLine>) {
+        (
+            self.inner.sources,
+            self.inner.sources_content,
+            self.inner.names,
+            self.inner.mapping_lines,
+        )
+    }
+
+    // Create a sourcemap from parts returned by into_parts,
+    // every source and name index in the mapping lines has to exist in the sources and names
+    // and there can't be more sources content than sources
+    pub fn from_parts(
+        project_root: &str,
+        sources: Vec<String>,
+        sources_content: Vec<String>,
+        names: Vec<String>,
+        mapping_lines: Vec<MappingLine>,
+    ) -> Result<SourceMap, SourceMapError> {
+        if sources_content.len() > sources.len() {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::SourceOutOfRange,
+                "sources_content has more entries than sources",
+            ));
+        }
+
+        for mapping_line in mapping_lines.iter() {
+            for mapping in mapping_line.mappings.iter() {
+                if let Some(original) = &mapping.original {
+                    if original.source as usize >= sources.len() {
+                        return Err(SourceMapError::new(SourceMapErrorType::SourceOutOfRange));
+                    }
+
+                    if matches!(original.name, Some(name) if name as usize >= names.len()) {
+                        return Err(SourceMapError::new(SourceMapErrorType::NameOutOfRange));
+                    }
+                }
+            }
+        }
+
+        Ok(SourceMap {
+            project_root: String::from(project_root),
+            name_dedup: NameDedup::default(),
+            content_loader: None,
+            inner: SourceMapInner {
+                sources,
+                sources_content,
+                names,
+                mapping_lines,
+                debug_id: None,
+            },
+        })
+    }
+
+    // Append an incremental chunk to a buffer, containing only the mapping lines starting at since_line.
+    // Applying all chunks of a buffer in order using apply_buffer_chunks results in the latest state of the sourcemap.
+    pub fn append_to_buffer(
+        &self,
+        existing: &mut Vec<u8>,
+        since_line: u32,
+    ) -> Result<(), SourceMapError> {
+        let chunk = SourceMapChunk {
+            since_line,
+            sources: self.inner.sources.clone(),
+            sources_content: self.inner.sources_content.clone(),
+            names: self.inner.names.clone(),
+            mapping_lines: self
+                .inner
+                .mapping_lines
+                .iter()
+                .skip(since_line as usize)
+                .cloned()
+                .collect(),
+        };
+
+        let mut payload = AlignedVec::new();
+        let mut serializer = AlignedSerializer::new(&mut payload);
+        serializer.serialize_value(&chunk)?;
+
+        existing.reserve(BUFFER_CHUNK_HEADER_SIZE + payload.len());
+        existing.extend_from_slice(BUFFER_CHUNK_MAGIC);
+        existing.extend_from_slice(&BUFFER_CHUNK_VERSION.to_le_bytes());
+        existing.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        existing.extend_from_slice(payload.as_slice());
+        Ok(())
+    }
+
+    // Apply all incremental chunks in a buffer created by append_to_buffer
+    pub fn apply_buffer_chunks(&mut self, buf: &[u8]) -> Result<(), SourceMapError> {
+        let mut remaining = buf;
+        while !remaining.is_empty() {
+            if remaining.len() < BUFFER_CHUNK_HEADER_SIZE || &remaining[0..4] != BUFFER_CHUNK_MAGIC
+            {
+                return Err(SourceMapError::new_with_reason(
+                    SourceMapErrorType::BufferError,
+                    "invalid incremental buffer chunk header",
+                ));
+            }
+
+            let version = u32::from_le_bytes(remaining[4..8].try_into().unwrap());
+            if version != BUFFER_CHUNK_VERSION {
+                return Err(SourceMapError::new_with_reason(
+                    SourceMapErrorType::BufferError,
+                    "unsupported incremental buffer chunk version",
+                ));
+            }
+
+            let payload_len = u32::from_le_bytes(remaining[8..12].try_into().unwrap()) as usize;
+            let payload_end = BUFFER_CHUNK_HEADER_SIZE + payload_len;
+            if remaining.len() < payload_end {
+                return Err(SourceMapError::new_with_reason(
+                    SourceMapErrorType::BufferError,
+                    "incremental buffer chunk is truncated",
+                ));
+            }
+
+            // The payload has to be aligned before it can be read
+            let mut payload = AlignedVec::with_capacity(payload_len);
+            payload.extend_from_slice(&remaining[BUFFER_CHUNK_HEADER_SIZE..payload_end]);
+            let archived = unsafe { archived_root::<SourceMapChunk>(payload.as_slice()) };
+            let mut deserializer = AllocDeserializer;
+            let mapping_lines: Vec<MappingLine> =
+                archived.mapping_lines.deserialize(&mut deserializer)?;
+
+            self.inner.sources = deserialize_strings(&archived.sources);
+            self.inner.sources_content = deserialize_strings(&archived.sources_content);
+            self.inner.names = deserialize_strings(&archived.names);
+            let since_line = archived.since_line as usize;
+            if since_line > 0 {
+                self.ensure_lines(since_line - 1);
+            }
+            self.inner.mapping_lines.truncate(since_line);
+            self.inner.mapping_lines.extend(mapping_lines);
+
+            remaining = &remaining[payload_end..];
+        }
+
+        Ok(())
+    }
+
+    // Sources of the added sourcemap that don't exist yet get appended in the order of the added sourcemap,
+    // existing sources keep their index. The same inputs always result in the same sources order.
+    pub fn add_sourcemap(
+        &mut self,
+        sourcemap: &mut SourceMap,
+        line_offset: i64,
+    ) -> Result<(), SourceMapError> {
+        let sources = std::mem::take(&mut sourcemap.inner.sources);
+        // Sources of a sourcemap with another project root are relative to that root, so rebase them onto ours
+        let rebase_sources = sourcemap.project_root != self.project_root;
+        let relative_sources: Vec<String> = sources
+            .iter()
+            .map(|s| {
+                if rebase_sources {
+                    let absolute_source = make_absolute_path(sourcemap.project_root.as_str(), s);
+                    make_relative_path(self.project_root.as_str(), absolute_source.as_str())
+                } else {
+                    make_relative_path(self.project_root.as_str(), s)
+                }
+            })
+            .collect();
+        let source_indexes = append_deduplicated(&mut self.inner.sources, relative_sources);
+
+        let names = std::mem::take(&mut sourcemap.inner.names);
+        let names_indexes = self.append_names(names);
+
+        self.inner
+            .sources_content
+            .reserve(sourcemap.inner.sources_content.len());
+        let sources_content = std::mem::take(&mut sourcemap.inner.sources_content);
+        // A source without content in the added map keeps the content it already has in this map
+        for (i, source_content_str) in sources_content
+            .iter()
+            .enumerate()
+            .filter(|(_, content)| !content.is_empty())
+        {
+            if let Some(source_index) = source_indexes.get(i) {
+                self.set_source_content(*source_index as usize, source_content_str)?;
+            }
+        }
+
+        let mapping_lines = std::mem::take(&mut sourcemap.inner.mapping_lines);
+        for (line, mapping_line) in mapping_lines.into_iter().enumerate() {
+            let generated_line = (line as i64) + line_offset;
+            if generated_line >= 0 {
+                let mut line = mapping_line;
+                for mapping in line.mappings.iter_mut() {
+                    match &mut mapping.original {
+                        Some(original_mapping_location) => {
+                            original_mapping_location.source = match source_indexes
+                                .get(original_mapping_location.source as usize)
+                            {
+                                Some(new_source_index) => *new_source_index,
+                                None => {
+                                    return Err(SourceMapError::new(
+                                        SourceMapErrorType::SourceOutOfRange,
+                                    ));
+                                }
+                            };
+
+                            original_mapping_location.name = match original_mapping_location.name {
+                                Some(name_index) => match names_indexes.get(name_index as usize) {
+                                    Some(new_name_index) => Some(*new_name_index),
+                                    None => {
+                                        return Err(SourceMapError::new(
+                                            SourceMapErrorType::NameOutOfRange,
+                                        ));
+                                    }
+                                },
+                                None => None,
+                            };
+                        }
+                        None => {}
+                    }
+                }
+
+                self.ensure_lines(generated_line as usize);
+                self.inner.mapping_lines[generated_line as usize] = line;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Generated positions that have a mapping in both sourcemaps which point to different original locations,
+    // sorted by position. Sources and names are compared by value since the indexes differ between sourcemaps.
+    pub fn conflicts_with(&self, other: &SourceMap) -> Vec<(u32, u32)> {
+        fn resolve(
+            map: &SourceMap,
+            original: Option<OriginalLocation>,
+        ) -> Option<(Option<&str>, u32, u32, Option<&str>)> {
+            original.map(|original| {
+                (
+                    map.get_source(original.source).ok(),
+                    original.original_line,
+                    original.original_column,
+                    original.name.and_then(|name| map.get_name(name).ok()),
+                )
+            })
+        }
+
+        let mut conflicts = Vec::new();
+        for (generated_line, (line, other_line)) in self
+            .inner
+            .mapping_lines
+            .iter()
+            .zip(other.inner.mapping_lines.iter())
+            .enumerate()
+        {
+            if line.mappings.is_empty() || other_line.mappings.is_empty() {
+                continue;
+            }
+
+            let other_originals: HashMap<u32, Option<OriginalLocation>> = other_line
+                .mappings
+                .iter()
+                .map(|m| (m.generated_column, m.original))
+                .collect();
+            for mapping in line.mappings.iter() {
+                if let Some(other_original) = other_originals.get(&mapping.generated_column) {
+                    if resolve(self, mapping.original) != resolve(other, *other_original) {
+                        conflicts.push((generated_line as u32, mapping.generated_column));
+                    }
+                }
+            }
+        }
+
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        conflicts
+    }
+
+    // Merge the mappings of another sourcemap into this one at the same generated positions.
+    // For every position that has a mapping in both sourcemaps resolve decides which mapping is kept,
+    // it gets the existing mapping and the other mapping (both using the source and name indexes of this sourcemap)
+    // and returns the mapping to keep or None to remove the mapping at that position,
+    // returning a mapping at another generated position is an error.
+    // Positions that only have a mapping in one of the sourcemaps are kept as is.
+    // Nothing changes if merging fails, sources and names are only added once all lines are merged.
+    pub fn merge_with<F>(&mut self, other: &SourceMap, mut resolve: F) -> Result<(), SourceMapError>
+    where
+        F: FnMut(&Mapping, &Mapping) -> Option<Mapping>,
+    {
+        // Indexes the sources and names of other get in this sourcemap, same as add_source and add_name would return
+        let mut new_sources: Vec<String> = Vec::new();
+        let source_indexes: Vec<u32> = other
+            .inner
+            .sources
+            .iter()
+            .map(|source| {
+                let relative_source = make_relative_path(self.project_root.as_str(), source);
+                match self
+                    .inner
+                    .sources
+                    .iter()
+                    .chain(new_sources.iter())
+                    .position(|s| relative_source.eq(s))
+                {
+                    Some(i) => i as u32,
+                    None => {
+                        new_sources.push(relative_source);
+                        (self.inner.sources.len() + new_sources.len() - 1) as u32
+                    }
+                }
+            })
+            .collect();
+        let mut new_names: Vec<String> = Vec::new();
+        let names_indexes: Vec<u32> = other
+            .inner
+            .names
+            .iter()
+            .map(|name| {
+                let existing = match self.name_dedup {
+                    NameDedup::None => None,
+                    _ => self
+                        .inner
+                        .names
+                        .iter()
+                        .chain(new_names.iter())
+                        .position(|s| name.eq(s)),
+                };
+                match existing {
+                    Some(i) => i as u32,
+                    None => {
+                        new_names.push(name.clone());
+                        (self.inner.names.len() + new_names.len() - 1) as u32
+                    }
+                }
+            })
+            .collect();
+
+        let mut merged_lines: Vec<(usize, MappingLine)> = Vec::new();
+        for (generated_line, other_line) in other.inner.mapping_lines.iter().enumerate() {
+            if other_line.mappings.is_empty() {
+                continue;
+            }
+
+            let mut incoming = Vec::with_capacity(other_line.mappings.len());
+            for mapping in other_line.mappings.iter() {
+                let mut mapping = *mapping;
+                if let Some(original) = &mut mapping.original {
+                    original.source = *source_indexes
+                        .get(original.source as usize)
+                        .ok_or_else(|| SourceMapError::new(SourceMapErrorType::SourceOutOfRange))?;
+                    original.name = match original.name {
+                        Some(name) => Some(*names_indexes.get(name as usize).ok_or_else(|| {
+                            SourceMapError::new(SourceMapErrorType::NameOutOfRange)
+                        })?),
+                        None => None,
+                    };
+                }
+                incoming.push(mapping);
+            }
+            sorted_last_per_column(&mut incoming, other_line.is_sorted);
+
+            let mut existing_mappings = Vec::new();
+            if let Some(line) = self.inner.mapping_lines.get(generated_line) {
+                existing_mappings.extend_from_slice(&line.mappings);
+                sorted_last_per_column(&mut existing_mappings, line.is_sorted);
+            }
+
+            // Walk both lines in column order, only columns present in both lines go through resolve
+            let mut merged = Vec::with_capacity(existing_mappings.len() + incoming.len());
+            let mut existing = existing_mappings.into_iter().peekable();
+            for mapping in incoming {
+                while let Some(existing_mapping) =
+                    existing.next_if(|m| m.generated_column < mapping.generated_column)
+                {
+                    merged.push(existing_mapping);
+                }
+
+                let existing_mapping =
+                    match existing.next_if(|m| m.generated_column == mapping.generated_column) {
+                        Some(existing_mapping) => existing_mapping,
+                        None => {
+                            merged.push(mapping);
+                            continue;
+                        }
+                    };
+
+                let position = |original| Mapping {
+                    generated_line: generated_line as u32,
+                    generated_column: mapping.generated_column,
+                    original,
+                };
+                let existing_mapping = position(existing_mapping.original);
+                if let Some(resolved) = resolve(&existing_mapping, &position(mapping.original)) {
+                    if resolved.generated_line != existing_mapping.generated_line
+                        || resolved.generated_column != existing_mapping.generated_column
+                    {
+                        return Err(SourceMapError::new_with_reason(
+                            SourceMapErrorType::InvalidMapping,
+                            &format!(
+                                "resolved mapping {}:{} does not match conflicting position {}:{}",
+                                resolved.generated_line,
+                                resolved.generated_column,
+                                existing_mapping.generated_line,
+                                existing_mapping.generated_column
+                            ),
+                        ));
+                    }
+                    merged.push(LineMapping {
+                        generated_column: resolved.generated_column,
+                        original: resolved.original,
+                    });
+                }
+            }
+            merged.extend(existing);
+
+            merged_lines.push((
+                generated_line,
+                MappingLine {
+                    last_column: merged.last().map(|m| m.generated_column).unwrap_or(0),
+                    mappings: merged,
+                    is_sorted: true,
+                },
+            ));
+        }
+
+        self.inner.sources.extend(new_sources);
+        self.inner.names.extend(new_names);
+        for (source_index, content) in source_indexes
+            .iter()
+            .zip(other.inner.sources_content.iter())
+        {
+            let has_content = match self.inner.sources_content.get(*source_index as usize) {
+                Some(existing) => !existing.is_empty(),
+                None => false,
+            };
+            if !content.is_empty() && !has_content {
+                self.set_source_content(*source_index as usize, content)?;
+            }
+        }
+
+        for (generated_line, line) in merged_lines {
+            self.ensure_lines(generated_line);
+            self.inner.mapping_lines[generated_line] = line;
+        }
+
+        Ok(())
+    }
+
+    // Append a sourcemap after the last generated line of this sourcemap, generated_line_count is the
+    // amount of generated lines the appended code spans, this can be more than the mapped lines if the
+    // appended code ends with unmapped lines. If it is not provided the mapped lines are used instead.
+    // Returns the generated extent after appending, see generated_extent.
+    pub fn append_sourcemap(
+        &mut self,
+        sourcemap: &mut SourceMap,
+        generated_line_count: Option<u32>,
+    ) -> Result<(u32, u32), SourceMapError> {
+        let line_offset = self.inner.mapping_lines.len();
+        let line_count = match generated_line_count {
+            Some(line_count) => line_count as usize,
+            None => sourcemap.inner.mapping_lines.len(),
+        };
+
+        // The appended lines all come after the existing ones, so only an appended sourcemap
+        // without mappings leaves the extent at the existing mappings
+        let extent = sourcemap
+            .inner
+            .mapping_lines
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(generated_line, line)| {
+                line.mappings
+                    .iter()
+                    .map(|m| m.generated_column)
+                    .max()
+                    .map(|generated_column| {
+                        ((line_offset + generated_line) as u32, generated_column)
+                    })
+            });
+        let extent = match extent {
+            Some(extent) => extent,
+            None => self.generated_extent(),
+        };
+
+        self.add_sourcemap(sourcemap, line_offset as i64)?;
+        if line_count > 0 {
+            self.ensure_lines(line_offset + line_count - 1);
+        }
+
+        Ok(extent)
+    }
+
+    // Split this sourcemap into a sourcemap per source, keyed by the source index in this sourcemap.
+    // Every sub map only contains the mappings pointing into that source, which becomes source 0.
+    pub fn split_by_source(&self) -> HashMap<u32, SourceMap> {
+        let mut maps: HashMap<u32, SourceMap> = HashMap::new();
+        for (generated_line, mapping_line) in self.inner.mapping_lines.iter().enumerate() {
+            for mapping in mapping_line.mappings.iter() {
+                let original = match mapping.original {
+                    Some(original) => original,
+                    None => continue,
+                };
+
+                let source = match self.inner.sources.get(original.source as usize) {
+                    Some(source) => source,
+                    None => continue,
+                };
+
+                let map = maps.entry(original.source).or_insert_with(|| {
+                    let mut map = SourceMap::new(self.project_root.as_str());
+                    map.inner.sources.push(source.clone());
+                    if let Some(content) = self.inner.sources_content.get(original.source as usize)
+                    {
+                        map.inner.sources_content.push(content.clone());
+                    }
+                    map
+                });
+
+                let name = original
+                    .name
+                    .and_then(|n| self.inner.names.get(n as usize))
+                    .map(|name| map.add_name(name));
+
+                map.add_mapping(
+                    generated_line as u32,
+                    mapping.generated_column,
+                    Some(OriginalLocation::new(
+                        original.original_line,
+                        original.original_column,
+                        0,
+                        name,
+                    )),
+                );
+            }
+        }
+        maps
+    }
+
+    // All mappings grouped by their source index, sorted by generated position.
+    // Mappings without an original location don't belong to a source and are left out.
+    pub fn mappings_by_source(&self) -> BTreeMap<u32, Vec<Mapping>> {
+        let mut mappings_by_source: BTreeMap<u32, Vec<Mapping>> = BTreeMap::new();
+        for mapping in self.get_mappings() {
+            if let Some(original) = mapping.original {
+                mappings_by_source
+                    .entry(original.source)
+                    .or_default()
+                    .push(mapping);
+            }
+        }
+        mappings_by_source
+    }
+
+    pub fn extends(&mut self, original_sourcemap: &mut SourceMap) -> Result<(), SourceMapError> {
+        self.compose(original_sourcemap, ComposeOptions::default())
+    }
+
+    // Like extends, but options decide what happens to mappings whose position in this map's sources
+    // has no mapping in original_sourcemap
+    pub fn compose(
+        &mut self,
+        original_sourcemap: &mut SourceMap,
+        options: ComposeOptions,
+    ) -> Result<(), SourceMapError> {
+        self.inner
+            .sources
+            .reserve(original_sourcemap.inner.sources.len());
+        let mut source_indexes = Vec::with_capacity(original_sourcemap.inner.sources.len());
+        for s in original_sourcemap.inner.sources.iter() {
+            source_indexes.push(self.add_source(s));
+        }
+
+        self.inner
+            .names
+            .reserve(original_sourcemap.inner.names.len());
+        let mut names_indexes = Vec::with_capacity(original_sourcemap.inner.names.len());
+        for n in original_sourcemap.inner.names.iter() {
+            names_indexes.push(self.add_name(n));
+        }
+
+        self.inner
+            .sources_content
+            .reserve(original_sourcemap.inner.sources_content.len());
+        for (i, source_content_str) in original_sourcemap
+            .inner
+            .sources_content
+            .iter()
+            .enumerate()
+            .filter(|(_, content)| !content.is_empty())
+        {
+            if let Some(source_index) = source_indexes.get(i) {
+                self.set_source_content(*source_index as usize, source_content_str)?;
+            }
+        }
+
+        for line_content in self.inner.mapping_lines.iter_mut() {
+            let mut dropped: Vec<usize> = Vec::new();
+            for (index, mapping) in line_content.mappings.iter_mut().enumerate() {
+                let original_location = match mapping.original {
+                    Some(original_location) => original_location,
+                    None => continue,
+                };
+
+                let found_location = original_sourcemap
+                    .find_closest_mapping(
+                        original_location.original_line,
+                        original_location.original_column,
+                    )
+                    .and_then(|original_mapping| original_mapping.original);
+                match found_location {
+                    Some(original_mapping_location) => {
+                        mapping.original = Some(OriginalLocation::new(
+                            original_mapping_location.original_line,
+                            original_mapping_location.original_column,
+                            match source_indexes.get(original_mapping_location.source as usize) {
+                                Some(new_source_index) => *new_source_index,
+                                None => {
+                                    return Err(SourceMapError::new(
+                                        SourceMapErrorType::SourceOutOfRange,
+                                    ));
+                                }
+                            },
+                            match original_mapping_location.name {
+                                Some(name_index) => match names_indexes.get(name_index as usize) {
+                                    Some(new_name_index) => Some(*new_name_index),
+                                    None => {
+                                        return Err(SourceMapError::new(
+                                            SourceMapErrorType::NameOutOfRange,
+                                        ));
+                                    }
+                                },
+                                None => None,
+                            },
+                        ));
+                    }
+                    None => match options.on_missing {
+                        MissingMapping::Unmapped => mapping.original = None,
+                        MissingMapping::KeepIntermediate => {}
+                        MissingMapping::Drop => dropped.push(index),
+                    },
+                }
+            }
+
+            if !dropped.is_empty() {
+                // dropped is in ascending order, so a cursor through it is enough to find the dropped mappings
+                let mut index = 0;
+                let mut dropped = dropped.into_iter().peekable();
+                line_content.mappings.retain(|_| {
+                    let keep = dropped.peek() != Some(&index);
+                    if !keep {
+                        dropped.next();
+                    }
+                    index += 1;
+                    keep
+                });
+                line_content.last_column = line_content
+                    .mappings
+                    .last()
+                    .map(|m| m.generated_column)
+                    .unwrap_or(0);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add_vlq_map(
+        &mut self,
+        input: &[u8],
+        sources: Vec<&str>,
+        sources_content: Vec<&str>,
+        names: Vec<&str>,
+        line_offset: i64,
+        column_offset: i64,
+    ) -> Result<(), SourceMapError> {
+        let (source_indexes, name_indexes) =
+            self.add_vlq_map_tables(sources, sources_content, names)?;
+        self.add_vlq_mappings(
+            input.iter().cloned(),
+            &source_indexes,
+            &name_indexes,
+            line_offset,
+            column_offset,
+        )
+    }
+
+    // Same as add_vlq_map but reads the mappings from a reader, so the mappings never have to be fully buffered
+    pub fn add_vlq_reader<R>(
+        &mut self,
+        reader: R,
+        sources: Vec<&str>,
+        sources_content: Vec<&str>,
+        names: Vec<&str>,
+        line_offset: i64,
+        column_offset: i64,
+    ) -> Result<(), SourceMapError>
+    where
+        R: io::Read,
+    {
+        let (source_indexes, name_indexes) =
+            self.add_vlq_map_tables(sources, sources_content, names)?;
+        let mut input = ReadBytes::new(reader);
+        self.add_vlq_mappings(
+            &mut input,
+            &source_indexes,
+            &name_indexes,
+            line_offset,
+            column_offset,
+        )?;
+
+        match input.error {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn add_vlq_map_tables(
+        &mut self,
+        sources: Vec<&str>,
+        sources_content: Vec<&str>,
+        names: Vec<&str>,
+    ) -> Result<(Vec<u32>, Vec<u32>), SourceMapError> {
+        let source_indexes: Vec<u32> = self.add_sources(sources);
+        let name_indexes: Vec<u32> = self.add_names(names);
+
+        // sources_content can be shorter or longer than sources, content without a matching source is ignored
+        // and empty content is treated as no content so it doesn't overwrite existing content
+        for (source_index, source_content) in source_indexes.iter().zip(sources_content.iter()) {
+            let source_index = *source_index as usize;
+            if !source_content.is_empty() || source_index >= self.inner.sources_content.len() {
+                self.set_source_content(source_index, source_content)?;
+            }
+        }
+
+        Ok((source_indexes, name_indexes))
+    }
+
+    fn add_vlq_mappings<I>(
+        &mut self,
+        input: I,
+        source_indexes: &[u32],
+        name_indexes: &[u32],
+        line_offset: i64,
+        column_offset: i64,
+    ) -> Result<(), SourceMapError>
+    where
+        I: Iterator<Item = u8>,
+    {
+        let mut generated_line: i64 = line_offset;
+        let mut generated_column: i64 = column_offset;
+        let mut original_line = 0;
+        let mut original_column = 0;
+        let mut source = 0;
+        let mut name = 0;
+
+        let mut input = input.peekable();
+        while let Some(byte) = input.peek().cloned() {
+            match byte {
+                b';' => {
+                    generated_line += 1;
+                    generated_column = column_offset;
+                    input.next().unwrap();
+                }
+                b',' => {
+                    input.next().unwrap();
+                }
+                _ => {
+                    // First is a generated column that is always present.
+                    read_relative_vlq(&mut generated_column, &mut input)?;
+
+                    // Read source, original line, and original column if the
+                    // mapping has them.
+                    let original = if input.peek().cloned().map_or(true, is_mapping_separator) {
+                        None
+                    } else {
+                        read_relative_vlq(&mut source, &mut input)?;
+                        read_relative_vlq(&mut original_line, &mut input)?;
+                        read_relative_vlq(&mut original_column, &mut input)?;
+                        Some(OriginalLocation::new(
+                            original_line as u32,
+                            original_column as u32,
+                            match source_indexes.get(source as usize) {
+                                Some(v) => *v,
+                                None => {
+                                    return Err(SourceMapError::new(
+                                        SourceMapErrorType::SourceOutOfRange,
+                                    ));
+                                }
+                            },
+                            if input.peek().cloned().map_or(true, is_mapping_separator) {
+                                None
+                            } else {
+                                read_relative_vlq(&mut name, &mut input)?;
+                                Some(match name_indexes.get(name as usize) {
+                                    Some(v) => *v,
+                                    None => {
+                                        return Err(SourceMapError::new(
+                                            SourceMapErrorType::NameOutOfRange,
+                                        ));
+                                    }
+                                })
+                            },
+                        ))
+                    };
+
+                    if generated_line >= 0 {
+                        self.add_mapping(generated_line as u32, generated_column as u32, original);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the amount of mappings that got shifted, 0 if the line doesn't exist or no mapping was at or after the column
+    pub fn offset_columns(
+        &mut self,
+        generated_line: u32,
+        generated_column: u32,
+        generated_column_offset: i64,
+    ) -> Result<usize, SourceMapError> {
+        self.offset_columns_with_underflow(
+            generated_line,
+            generated_column,
+            generated_column_offset,
+            ColumnUnderflow::Error,
+        )
+    }
+
+    // Like offset_columns, but underflow decides what happens to mappings that would end up before column 0
+    pub fn offset_columns_with_underflow(
+        &mut self,
+        generated_line: u32,
+        generated_column: u32,
+        generated_column_offset: i64,
+        underflow: ColumnUnderflow,
+    ) -> Result<usize, SourceMapError> {
+        if generated_column_offset == 0 {
+            return Ok(0);
+        }
+
+        match self.inner.mapping_lines.get_mut(generated_line as usize) {
+            Some(line) => line.offset_columns_with_underflow(
+                generated_column,
+                generated_column_offset,
+                underflow,
+            ),
+            None => Ok(0),
+        }
+    }
+
+    // Shift the original line of every mapping into a source, e.g. after a banner got prepended to that source.
+    // Nothing changes if any of the original lines would become negative.
+    pub fn offset_original_lines(
+        &mut self,
+        source_index: u32,
+        line_offset: i64,
+    ) -> Result<(), SourceMapError> {
+        if line_offset == 0 {
+            return Ok(());
+        }
+
+        for mapping_line in self.inner.mapping_lines.iter() {
+            for mapping in mapping_line.mappings.iter() {
+                if let Some(original) = &mapping.original {
+                    if original.source != source_index {
+                        continue;
+                    }
+
+                    let original_line = original.original_line as i64 + line_offset;
+                    if original_line < 0 {
+                        return Err(SourceMapError::new_with_reason(
+                            SourceMapErrorType::UnexpectedNegativeNumber,
+                            "original_line + line_offset cannot be negative",
+                        ));
+                    }
+
+                    if original_line > (u32::MAX as i64) {
+                        return Err(SourceMapError::new(
+                            SourceMapErrorType::UnexpectedlyBigNumber,
+                        ));
+                    }
+                }
+            }
+        }
+
+        for mapping_line in self.inner.mapping_lines.iter_mut() {
+            for mapping in mapping_line.mappings.iter_mut() {
+                if let Some(original) = &mut mapping.original {
+                    if original.source == source_index {
+                        original.original_line =
+                            (original.original_line as i64 + line_offset) as u32;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Apply multiple (generated_line, generated_column, generated_column_offset) offsets at once, the ops are grouped
+    // by line and every line is rewritten once. Columns refer to the line before any op got applied, a mapping is
+    // shifted by the offsets of all ops at or before its column and removed when a negative offset moves over it,
+    // which is the same as applying the ops one by one from right to left.
+    // Ops on a line can't share a column and a negative offset can't reach past the column of the op before it.
+    // Zero offsets are skipped and nothing changes if any op is invalid.
+    pub fn offset_columns_batch(&mut self, ops: &[(u32, u32, i64)]) -> Result<(), SourceMapError> {
+        let mut line_ops: BTreeMap<u32, Vec<(u32, i64)>> = BTreeMap::new();
+        for (generated_line, generated_column, generated_column_offset) in
+            ops.iter().filter(|op| op.2 != 0)
+        {
+            line_ops
+                .entry(*generated_line)
+                .or_default()
+                .push((*generated_column, *generated_column_offset));
+        }
+
+        for (generated_line, column_ops) in line_ops.iter_mut() {
+            column_ops.sort_unstable_by_key(|op| op.0);
+            let mut previous_column: Option<u32> = None;
+            let mut total_offset: i64 = 0;
+            for (generated_column, generated_column_offset) in column_ops.iter() {
+                let start_column = *generated_column as i64 + generated_column_offset;
+                if start_column < 0 {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::UnexpectedNegativeNumber,
+                        "column + column_offset cannot be negative",
+                    ));
+                }
+
+                if let Some(previous_column) = previous_column {
+                    if previous_column == *generated_column || start_column < previous_column as i64
+                    {
+                        return Err(SourceMapError::new_with_reason(
+                            SourceMapErrorType::InvalidMapping,
+                            &format!(
+                                "column offsets on line {} overlap at column {}",
+                                generated_line, generated_column
+                            ),
+                        ));
+                    }
+                }
+
+                total_offset += generated_column_offset;
+                if *generated_column as i64 + total_offset > (u32::MAX as i64) {
+                    return Err(SourceMapError::new_with_reason(
+                        SourceMapErrorType::UnexpectedlyBigNumber,
+                        "column + column_offset is too big",
+                    ));
+                }
+                previous_column = Some(*generated_column);
+            }
+        }
+
+        for (generated_line, column_ops) in line_ops {
+            let line = match self.inner.mapping_lines.get_mut(generated_line as usize) {
+                Some(line) => line,
+                None => continue,
+            };
+
+            line.ensure_sorted();
+            let mappings = std::mem::take(&mut line.mappings);
+            let mut next_op = 0;
+            let mut total_offset: i64 = 0;
+            for mapping in mappings {
+                while let Some((_, generated_column_offset)) = column_ops
+                    .get(next_op)
+                    .filter(|op| op.0 <= mapping.generated_column)
+                {
+                    total_offset += generated_column_offset;
+                    next_op += 1;
+                }
+
+                // Only the next op can move over this mapping, the ops don't overlap
+                if let Some((generated_column, generated_column_offset)) = column_ops.get(next_op) {
+                    if mapping.generated_column as i64
+                        >= *generated_column as i64 + generated_column_offset
+                    {
+                        continue;
+                    }
+                }
+
+                line.mappings.push(LineMapping {
+                    generated_column: (mapping.generated_column as i64 + total_offset) as u32,
+                    original: mapping.original,
+                });
+            }
+            line.last_column = line
+                .mappings
+                .last()
+                .map(|m| m.generated_column)
+                .unwrap_or(0);
+        }
+
+        Ok(())
+    }
+
+    // Move the mappings at the listed generated positions by (line_delta, column_delta).
+    // A moved mapping replaces any mapping already at its target position, when multiple mappings
+    // are moved to the same position the one that comes last in the deltas table wins.
+    // Nothing changes if any target position is out of range.
+    pub fn apply_position_deltas(
+        &mut self,
+        deltas: &BTreeMap<(u32, u32), (i64, i64)>,
+    ) -> Result<(), SourceMapError> {
+        for ((generated_line, generated_column), (line_delta, column_delta)) in deltas.iter() {
+            let target_line = *generated_line as i64 + line_delta;
+            let target_column = *generated_column as i64 + column_delta;
+            if target_line < 0 || target_column < 0 {
+                return Err(SourceMapError::new_with_reason(
+                    SourceMapErrorType::UnexpectedNegativeNumber,
+                    "position + delta cannot be negative",
+                ));
+            }
+
+            if target_line > (u32::MAX as i64) || target_column > (u32::MAX as i64) {
+                return Err(SourceMapError::new(
+                    SourceMapErrorType::UnexpectedlyBigNumber,
+                ));
+            }
+        }
+
+        // Take the moved mappings out of every line that has deltas, in the order of the deltas table
+        let mut moved: Vec<(u32, u32, Option<OriginalLocation>)> = Vec::new();
+        let mut source_lines: Vec<u32> = deltas.keys().map(|(line, _)| *line).collect();
+        source_lines.dedup();
+        for generated_line in source_lines {
+            let line = match self.inner.mapping_lines.get_mut(generated_line as usize) {
+                Some(line) => line,
+                None => continue,
+            };
+
+            line.ensure_sorted();
+            line.mappings.retain(
+                |m| match deltas.get(&(generated_line, m.generated_column)) {
+                    Some((line_delta, column_delta)) => {
+                        moved.push((
+                            (generated_line as i64 + line_delta) as u32,
+                            (m.generated_column as i64 + column_delta) as u32,
+                            m.original,
+                        ));
+                        false
+                    }
+                    None => true,
+                },
+            );
+            line.last_column = line
+                .mappings
+                .last()
+                .map(|m| m.generated_column)
+                .unwrap_or(0);
+        }
+
+        // Group the moved mappings by target, the stable sort keeps the deltas order so the last one wins
+        moved.sort_by_key(|(line, column, _)| (*line, *column));
+        moved.dedup_by(|next, kept| {
+            if (next.0, next.1) != (kept.0, kept.1) {
+                return false;
+            }
+
+            *kept = *next;
+            true
+        });
+
+        let mut remaining = &moved[..];
+        while let Some((target_line, _, _)) = remaining.first() {
+            let target_line = *target_line;
+            let count = remaining
+                .iter()
+                .take_while(|(line, _, _)| *line == target_line)
+                .count();
+            let (targets, rest) = remaining.split_at(count);
+            remaining = rest;
+
+            self.ensure_lines(target_line as usize);
+            let line = &mut self.inner.mapping_lines[target_line as usize];
+            line.mappings.retain(|m| {
+                targets
+                    .binary_search_by_key(&m.generated_column, |(_, column, _)| *column)
+                    .is_err()
+            });
+            line.last_column = line
+                .mappings
+                .last()
+                .map(|m| m.generated_column)
+                .unwrap_or(0);
+            for (_, target_column, original) in targets {
+                line.add_mapping(*target_column, *original);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Shift all lines starting at generated_line by generated_line_offset, the mappings on generated_line itself move as well.
+    // A positive offset inserts empty lines before generated_line,
+    // a negative offset removes the lines right before generated_line.
+    pub fn offset_lines(
+        &mut self,
+        generated_line: u32,
+        generated_line_offset: i64,
+    ) -> Result<(), SourceMapError> {
+        if generated_line_offset == 0 || self.inner.mapping_lines.is_empty() {
+            return Ok(());
+        }
+
+        let (start_line, overflowed) =
+            (generated_line as i64).overflowing_add(generated_line_offset);
+        if overflowed || start_line > (u32::MAX as i64) {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::UnexpectedlyBigNumber,
+                "line + line_offset cannot be bigger than u32::MAX",
+            ));
+        }
+
+        if start_line < 0 {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::UnexpectedNegativeNumber,
+                "line + line_offset cannot be negative",
+            ));
+        }
+
+        let line = generated_line as usize;
+        let abs_offset = generated_line_offset.abs() as usize;
+        if generated_line_offset > 0 {
+            if line > self.inner.mapping_lines.len() {
+                self.ensure_lines(line + abs_offset);
+            } else {
+                self.inner
+                    .mapping_lines
+                    .splice(line..line, (0..abs_offset).map(|_| MappingLine::new()));
+            }
+        } else {
+            // The lines in between get overwritten by the shifted lines, lines past the end don't exist
+            let lines_len = self.inner.mapping_lines.len();
+            let start_line = start_line as usize;
+            self.inner
+                .mapping_lines
+                .drain(start_line.min(lines_len)..line.min(lines_len));
+        }
+
+        Ok(())
+    }
+
+    // Like offset_lines, but before decides which line is the first one that gets shifted:
+    // generated_line when before is true, generated_line + 1 when it is false. A positive offset inserts
+    // empty lines right before that line and a negative offset removes the lines right before it, so when
+    // before is false generated_line itself never moves but is the first line a negative offset removes.
+    pub fn offset_lines_at(
+        &mut self,
+        generated_line: u32,
+        generated_line_offset: i64,
+        before: bool,
+    ) -> Result<(), SourceMapError> {
+        if before {
+            self.offset_lines(generated_line, generated_line_offset)
+        } else {
+            match generated_line.checked_add(1) {
+                Some(next_line) => self.offset_lines(next_line, generated_line_offset),
+                None => Ok(()),
+            }
+        }
+    }
+
+    // Remove all generated lines starting at max_line, used when the generated code gets truncated
+    pub fn truncate_lines(&mut self, max_line: u32) {
+        self.inner.mapping_lines.truncate(max_line as usize);
+    }
+
+    pub fn add_empty_map(
+        &mut self,
+        source: &str,
+        source_content: &str,
+        line_offset: i64,
+    ) -> Result<(), SourceMapError> {
+        let source_index = self.add_source(source);
+        self.set_source_content(source_index as usize, source_content)?;
+
+        for (line_count, _line) in source_content.lines().enumerate() {
+            let generated_line = (line_count as i64) + line_offset;
+            if generated_line >= 0 {
+                self.add_mapping(
                     generated_line as u32,
                     0,
-                    Some(OriginalLocation::new(
-                        line_count as u32,
-                        0,
-                        source_index,
-                        None,
-                    )),
-                )
+                    Some(OriginalLocation::new(
+                        line_count as u32,
+                        0,
+                        source_index,
+                        None,
+                    )),
+                )
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Iterates over all mappings of a sourcemap, line by line and sorted by column like get_mappings.
+// Lines that haven't been sorted yet get sorted into a copy when the iterator gets to them.
+pub struct MappingIter<'a> {
+    mapping_lines: &'a [MappingLine],
+    generated_line: usize,
+    sorted_line: Option<Vec<LineMapping>>,
+    index: usize,
+}
+
+impl<'a> MappingIter<'a> {
+    fn sort_line(&mut self) {
+        self.sorted_line = match self.mapping_lines.get(self.generated_line) {
+            Some(mapping_line) if !mapping_line.is_sorted => {
+                let mut sorted = mapping_line.mappings.clone();
+                sorted.sort_by_key(|m| m.generated_column);
+                Some(sorted)
+            }
+            _ => None,
+        };
+    }
+}
+
+impl<'a> Iterator for MappingIter<'a> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        while let Some(mapping_line) = self.mapping_lines.get(self.generated_line) {
+            let mappings = match &self.sorted_line {
+                Some(sorted) => sorted,
+                None => &mapping_line.mappings,
+            };
+            if let Some(mapping) = mappings.get(self.index).copied() {
+                self.index += 1;
+                return Some(Mapping {
+                    generated_line: self.generated_line as u32,
+                    generated_column: mapping.generated_column,
+                    original: mapping.original,
+                });
+            }
+
+            self.generated_line += 1;
+            self.index = 0;
+            self.sort_line();
+        }
+
+        None
+    }
+}
+
+impl<'a> IntoIterator for &'a SourceMap {
+    type Item = Mapping;
+    type IntoIter = MappingIter<'a>;
+
+    fn into_iter(self) -> MappingIter<'a> {
+        let mut iter = MappingIter {
+            mapping_lines: &self.inner.mapping_lines,
+            generated_line: 0,
+            sorted_line: None,
+            index: 0,
+        };
+        iter.sort_line();
+        iter
+    }
+}
+
+#[allow(non_fmt_panic)]
+#[test]
+fn test_buffers() {
+    let map = SourceMap::new("/");
+    let mut output = AlignedVec::new();
+    match map.to_buffer(&mut output) {
+        Ok(_) => {}
+        Err(err) => panic!(err),
+    }
+    match SourceMap::from_buffer("/", &output) {
+        Ok(map) => {
+            println!("{:?}", map)
+        }
+        Err(err) => panic!(err),
+    }
+}
+
+#[test]
+fn test_append_sourcemap_unmapped_lines() {
+    let mut map = SourceMap::new("/");
+    let mut first = SourceMap::new("/");
+    first.add_mapping(0, 0, None);
+    first.add_mapping(1, 0, None);
+    map.append_sourcemap(&mut first, None).unwrap();
+
+    // Six lines of generated code, only the first and third line have mappings
+    let mut second = SourceMap::new("/");
+    second.add_mapping(0, 0, None);
+    second.add_mapping(2, 4, None);
+    map.append_sourcemap(&mut second, Some(6)).unwrap();
+
+    let mut third = SourceMap::new("/");
+    third.add_mapping(0, 2, None);
+    map.append_sourcemap(&mut third, None).unwrap();
+
+    let generated_lines: Vec<u32> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.generated_line)
+        .collect();
+    assert_eq!(generated_lines, vec![0, 1, 2, 4, 8]);
+}
+
+#[test]
+fn test_stats() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    map.add_source("b.js");
+    let name = map.add_name("foo");
+    map.set_source_content(source as usize, "const foo = 1;")
+        .unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+    map.add_mapping(0, 6, Some(OriginalLocation::new(0, 6, source, None)));
+    map.add_mapping(2, 0, None);
+
+    assert_eq!(
+        map.stats(),
+        SourceMapStats {
+            sources: 2,
+            names: 1,
+            mappings: 3,
+            lines: 3,
+            with_content: 1,
+        }
+    );
+}
+
+#[test]
+fn test_absolutize_sources() {
+    let mut map = SourceMap::new("/project");
+    map.add_sources(vec![
+        "/project/src/a.js",
+        "/other/b.js",
+        "http://example.com/c.js",
+    ]);
+    assert_eq!(
+        map.get_sources(),
+        &vec!["src/a.js", "../other/b.js", "http://example.com/c.js"]
+    );
+
+    map.absolutize_sources().unwrap();
+    assert_eq!(
+        map.get_sources(),
+        &vec![
+            "/project/src/a.js",
+            "/other/b.js",
+            "http://example.com/c.js"
+        ]
+    );
+
+    let mut relative_root_map = SourceMap::new("project");
+    relative_root_map.add_source("a.js");
+    assert!(relative_root_map.absolutize_sources().is_err());
+}
+
+#[test]
+fn test_sources_as_file_urls() {
+    let mut map = SourceMap::new("/project");
+    map.add_sources(vec!["/project/src/my file.js", "http://example.com/c.js"]);
+    assert_eq!(
+        map.sources_as_file_urls(),
+        vec![
+            "file:///project/src/my%20file.js",
+            "http://example.com/c.js"
+        ]
+    );
+}
+
+#[test]
+fn test_offset_columns_batch() {
+    let build = || {
+        let mut map = SourceMap::new("/");
+        for line in 0..3 {
+            for column in [0, 10, 20, 30].iter() {
+                map.add_mapping(
+                    line,
+                    *column,
+                    Some(OriginalLocation::new(line, *column, 0, None)),
+                );
+            }
+        }
+        map
+    };
+    let columns = |map: &SourceMap| -> Vec<(u32, u32, u32)> {
+        map.get_mappings()
+            .iter()
+            .map(|m| {
+                (
+                    m.generated_line,
+                    m.generated_column,
+                    m.original.unwrap().original_column,
+                )
+            })
+            .collect()
+    };
+
+    // Ops that don't overlap give the same result as applying them one by one
+    let mut sequential_map = build();
+    sequential_map.offset_columns(0, 20, 5).unwrap();
+    sequential_map.offset_columns(1, 5, 0).unwrap();
+    sequential_map.offset_columns(2, 30, -4).unwrap();
+    let mut batch_map = build();
+    batch_map
+        .offset_columns_batch(&[(2, 30, -4), (1, 5, 0), (0, 20, 5)])
+        .unwrap();
+    assert_eq!(columns(&batch_map), columns(&sequential_map));
+
+    // Columns of ops on the same line refer to the line before the batch, from right to left
+    let mut sequential_map = build();
+    sequential_map.offset_columns(0, 30, -5).unwrap();
+    sequential_map.offset_columns(0, 10, 2).unwrap();
+    let mut batch_map = build();
+    batch_map
+        .offset_columns_batch(&[(0, 10, 2), (0, 30, -5)])
+        .unwrap();
+    assert_eq!(columns(&batch_map), columns(&sequential_map));
+    assert_eq!(
+        columns(&batch_map)[..4],
+        [(0, 0, 0), (0, 12, 10), (0, 22, 20), (0, 27, 30)]
+    );
+
+    // Nothing changes if any op fails
+    let mut map = build();
+    let err = map
+        .offset_columns_batch(&[(0, 20, 5), (1, 10, -11)])
+        .unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        SourceMapErrorType::UnexpectedNegativeNumber
+    ));
+    let err = map
+        .offset_columns_batch(&[(0, 10, 5), (0, 20, -15)])
+        .unwrap_err();
+    assert!(matches!(err.error_type, SourceMapErrorType::InvalidMapping));
+    assert_eq!(columns(&map), columns(&build()));
+}
+
+#[test]
+fn test_verify_against_generated() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, None);
+    map.add_mapping(0, 6, None);
+    map.add_mapping(1, 2, None);
+    assert!(map.verify_against_generated("const a;\r\nb;").is_ok());
+    assert!(map.verify_against_generated("const\nb;").is_err());
+    assert!(map.verify_against_generated("const a;").is_err());
+}
+
+#[test]
+fn test_verify_original_positions() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let source_without_content = map.add_source("b.js");
+    map.set_source_content(source as usize, "let a;\nlet b;")
+        .unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(1, 4, source, None)));
+    map.add_mapping(
+        0,
+        4,
+        Some(OriginalLocation::new(9, 0, source_without_content, None)),
+    );
+    assert!(map.verify_original_positions().is_ok());
+
+    map.add_mapping(1, 0, Some(OriginalLocation::new(1, 7, source, None)));
+    assert!(map.verify_original_positions().is_err());
+}
+
+#[test]
+fn test_split_by_source() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    let b = map.add_source("b.js");
+    map.set_source_content(b as usize, "let b;").unwrap();
+    let name = map.add_name("b");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 5, Some(OriginalLocation::new(0, 4, b, Some(name))));
+    map.add_mapping(1, 0, Some(OriginalLocation::new(1, 0, a, None)));
+    map.add_mapping(1, 3, None);
+
+    let maps = map.split_by_source();
+    assert_eq!(maps.len(), 2);
+
+    let map_a = &maps[&a];
+    assert_eq!(map_a.get_sources(), &vec!["a.js"]);
+    assert!(map_a.get_names().is_empty());
+    let lines_a: Vec<(u32, u32)> = map_a
+        .get_mappings()
+        .iter()
+        .map(|m| (m.generated_line, m.generated_column))
+        .collect();
+    assert_eq!(lines_a, vec![(0, 0), (1, 0)]);
+
+    let map_b = &maps[&b];
+    assert_eq!(map_b.get_sources(), &vec!["b.js"]);
+    assert_eq!(map_b.get_sources_content(), &vec!["let b;"]);
+    assert_eq!(map_b.get_names(), &vec!["b"]);
+    let mappings_b = map_b.get_mappings();
+    assert_eq!(mappings_b.len(), 1);
+    assert_eq!(mappings_b[0].generated_column, 5);
+    assert_eq!(mappings_b[0].original.unwrap().source, 0);
+    assert_eq!(mappings_b[0].original.unwrap().name, Some(0));
+}
+
+#[test]
+fn test_name_dedup() {
+    let mut map = SourceMap::new("/");
+    assert_eq!(map.add_names(vec!["a", "b", "a"]), vec![0, 1, 0]);
+    assert_eq!(map.get_names(), &vec!["a", "b"]);
+
+    let mut map = SourceMap::new("/");
+    map.name_dedup = NameDedup::None;
+    assert_eq!(map.add_names(vec!["a", "b", "a"]), vec![0, 1, 2]);
+    assert_eq!(map.get_names(), &vec!["a", "b", "a"]);
+}
+
+#[test]
+fn test_line_density_histogram() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, None);
+    map.add_mapping(0, 4, None);
+    map.add_mapping(0, 8, None);
+    map.add_mapping(2, 0, None);
+
+    let histogram = map.line_density_histogram();
+    assert_eq!(
+        histogram.into_iter().collect::<Vec<_>>(),
+        vec![(0, 3), (2, 1)]
+    );
+}
+
+#[test]
+fn test_truncate_lines() {
+    let mut map = SourceMap::new("/");
+    for line in 0..10 {
+        map.add_mapping(line, 0, None);
+    }
+
+    map.truncate_lines(5);
+    let generated_lines: Vec<u32> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.generated_line)
+        .collect();
+    assert_eq!(generated_lines, vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn test_add_vlq_reader() {
+    // Reader that only hands out a couple of bytes at a time
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+    }
+
+    impl<'a> io::Read for ChunkedReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = std::cmp::min(3, std::cmp::min(buf.len(), self.data.len()));
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    let mappings = b"AAAA;AAAA,EAAA,OAAO,CAAC,GAAR,CAAY,aAAZ,CAAA,CAAA;AAAA";
+    let mut buffered_map = SourceMap::new("/");
+    buffered_map
+        .add_vlq_map(mappings, vec!["a.js"], vec![], vec![], 0, 0)
+        .unwrap();
+    let mut streamed_map = SourceMap::new("/");
+    streamed_map
+        .add_vlq_reader(
+            io::BufReader::with_capacity(2, ChunkedReader { data: mappings }),
+            vec!["a.js"],
+            vec![],
+            vec![],
+            0,
+            0,
+        )
+        .unwrap();
+
+    let mut buffered_vlq: Vec<u8> = vec![];
+    buffered_map.write_vlq(&mut buffered_vlq).unwrap();
+    let mut streamed_vlq: Vec<u8> = vec![];
+    streamed_map.write_vlq(&mut streamed_vlq).unwrap();
+    assert_eq!(buffered_vlq, streamed_vlq);
+    assert_eq!(&streamed_vlq[..], &mappings[..]);
+}
+
+#[test]
+fn test_iterate_mappings() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, None);
+    map.add_mapping(0, 4, None);
+    map.add_mapping(3, 2, None);
+
+    let mut positions = Vec::new();
+    for mapping in &map {
+        positions.push((mapping.generated_line, mapping.generated_column));
+    }
+    assert_eq!(positions, vec![(0, 0), (0, 4), (3, 2)]);
+
+    // Lines that haven't been sorted yet come out in the same order as get_mappings
+    map.add_mapping(3, 0, None);
+    map.add_mapping(0, 2, None);
+    map.add_mapping(4, 1, None);
+    let mut positions = Vec::new();
+    for mapping in &map {
+        positions.push((mapping.generated_line, mapping.generated_column));
+    }
+    assert_eq!(
+        positions,
+        vec![(0, 0), (0, 2), (0, 4), (3, 0), (3, 2), (4, 1)]
+    );
+    assert_eq!(
+        positions,
+        map.get_mappings()
+            .iter()
+            .map(|m| (m.generated_line, m.generated_column))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_prepend_source() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    map.set_source_content(source as usize, "let a;").unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, None)));
+
+    assert_eq!(map.prepend_source("runtime.js", None), 0);
+    assert_eq!(map.get_sources(), &vec!["runtime.js", "a.js"]);
+    assert_eq!(map.get_sources_content(), &vec!["", "let a;"]);
+    assert_eq!(map.get_mappings()[0].original.unwrap().source, 1);
+
+    // An existing source moves to the front instead of getting added twice
+    let b = map.add_source("b.js");
+    map.add_mapping(1, 0, Some(OriginalLocation::new(0, 0, b, None)));
+    assert_eq!(map.prepend_source("a.js", None), 0);
+    assert_eq!(map.get_sources(), &vec!["a.js", "runtime.js", "b.js"]);
+    assert_eq!(map.get_sources_content(), &vec!["let a;", ""]);
+    assert_eq!(map.get_source_index("a.js").unwrap(), Some(0));
+    let sources: Vec<u32> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.original.unwrap().source)
+        .collect();
+    assert_eq!(sources, vec![0, 2]);
+
+    assert_eq!(map.prepend_source("b.js", Some("let b;")), 0);
+    assert_eq!(map.get_sources(), &vec!["b.js", "a.js", "runtime.js"]);
+    assert_eq!(map.get_sources_content(), &vec!["let b;", "let a;", ""]);
+}
+
+#[test]
+fn test_source_content_for_path() {
+    let mut map = SourceMap::new("/project");
+    let a = map.add_source("/project/a.js");
+    map.add_source("/project/b.js");
+    map.set_source_content(a as usize, "let a;").unwrap();
+
+    assert_eq!(map.source_content_for_path("/project/a.js"), Some("let a;"));
+    assert_eq!(map.source_content_for_path("a.js"), Some("let a;"));
+    assert_eq!(map.source_content_for_path("/project/b.js"), None);
+    assert_eq!(map.source_content_for_path("/project/c.js"), None);
+}
+
+#[test]
+fn test_extend_sorted() {
+    let mut map = SourceMap::new("/");
+    map.extend_sorted(vec![(0, 0), (0, 5), (2, 1), (2, 8)].into_iter().map(
+        |(generated_line, generated_column)| Mapping {
+            generated_line,
+            generated_column,
+            original: None,
+        },
+    ));
+
+    let positions: Vec<(u32, u32)> = map
+        .get_mappings()
+        .iter()
+        .map(|m| (m.generated_line, m.generated_column))
+        .collect();
+    assert_eq!(positions, vec![(0, 0), (0, 5), (2, 1), (2, 8)]);
+
+    // get_mappings sorts its output, so check the lines themselves are in order and marked as sorted
+    for (generated_line, line) in map.lines() {
+        assert!(line.is_sorted, "line {} is not sorted", generated_line);
+    }
+    let columns: Vec<_> = map
+        .lines()
+        .map(|(_, line)| {
+            line.columns()
+                .map(|m| m.generated_column)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(columns, vec![vec![0, 5], vec![], vec![1, 8]]);
+}
+
+#[test]
+fn test_add_sourcemap_names() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let mut appended = SourceMap::new("/");
+    let appended_source = appended.add_source("b.js");
+    let names = appended.add_names(vec!["b", "a"]);
+    appended.add_mapping(
+        0,
+        0,
+        Some(OriginalLocation::new(0, 0, appended_source, Some(names[1]))),
+    );
+
+    // Disjoint, the destination has no names yet
+    map.add_sourcemap(&mut appended, 0).unwrap();
+    assert_eq!(map.get_names(), &vec!["b", "a"]);
+    assert_eq!(map.get_mappings()[0].original.unwrap().name, Some(1));
+
+    // Overlapping, existing names are reused
+    let mut map = SourceMap::new("/");
+    let name = map.add_name("a");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+    let mut appended = SourceMap::new("/");
+    let appended_source = appended.add_source("b.js");
+    let names = appended.add_names(vec!["b", "a"]);
+    appended.add_mapping(
+        0,
+        0,
+        Some(OriginalLocation::new(0, 0, appended_source, Some(names[1]))),
+    );
+    map.add_sourcemap(&mut appended, 1).unwrap();
+    assert_eq!(map.get_names(), &vec!["a", "b"]);
+    let mapping_names: Vec<Option<u32>> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.original.unwrap().name)
+        .collect();
+    assert_eq!(mapping_names, vec![Some(0), Some(0)]);
+}
+
+#[test]
+fn test_incremental_buffer() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    for line in 0..4 {
+        map.add_mapping(line, 0, Some(OriginalLocation::new(line, 0, source, None)));
+    }
+
+    let mut buffer: Vec<u8> = Vec::new();
+    map.append_to_buffer(&mut buffer, 0).unwrap();
+
+    // Change the lines starting at line 2 and only write those
+    let name = map.add_name("foo");
+    map.truncate_lines(2);
+    map.add_mapping(2, 4, Some(OriginalLocation::new(8, 2, source, Some(name))));
+    map.add_mapping(5, 0, None);
+    map.append_to_buffer(&mut buffer, 2).unwrap();
+
+    let mut restored_map = SourceMap::new("/");
+    restored_map.apply_buffer_chunks(&buffer).unwrap();
+    let mut expected_vlq: Vec<u8> = vec![];
+    map.write_vlq(&mut expected_vlq).unwrap();
+    let mut restored_vlq: Vec<u8> = vec![];
+    restored_map.write_vlq(&mut restored_vlq).unwrap();
+    assert_eq!(restored_vlq, expected_vlq);
+    assert_eq!(restored_map.get_names(), &vec!["foo"]);
+
+    assert!(restored_map.apply_buffer_chunks(&buffer[1..]).is_err());
+}
+
+#[test]
+fn test_verify_line_count() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, None);
+    map.add_mapping(2, 0, None);
+    assert!(map.verify_line_count("a;\nb;\nc;\n").is_ok());
+    assert!(map.verify_line_count("a;\r\nb;\r\nc;\r\n").is_ok());
+    assert!(map.verify_line_count("a;\nb;\n").is_err());
+    assert!(map.verify_line_count("a;\r\nb;").is_err());
+}
+
+#[test]
+fn test_surrounding_mappings() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 4, None);
+    map.add_mapping(2, 2, None);
+    map.add_mapping(2, 10, None);
+    map.add_mapping(4, 1, None);
+
+    let position = |m: Option<Mapping>| m.map(|m| (m.generated_line, m.generated_column));
+    let (previous, next) = map.surrounding_mappings(2, 6);
+    assert_eq!(position(previous), Some((2, 2)));
+    assert_eq!(position(next), Some((2, 10)));
+
+    let (previous, next) = map.surrounding_mappings(2, 12);
+    assert_eq!(position(previous), Some((2, 10)));
+    assert_eq!(position(next), Some((4, 1)));
+
+    let (previous, next) = map.surrounding_mappings(2, 0);
+    assert_eq!(position(previous), Some((0, 4)));
+    assert_eq!(position(next), Some((2, 2)));
+
+    let (previous, next) = map.surrounding_mappings(9, 0);
+    assert_eq!(position(previous), Some((4, 1)));
+    assert_eq!(position(next), None);
+}
+
+#[test]
+fn test_from_indexed_mappings() {
+    let sourcemap = SourceMap::from_indexed_mappings(
+        "/",
+        &[0, 0, 0, 0, 0, -1, 0, 6, 1, 2, 1, 0, 1, 0, -1, -1, -1, -1],
+        vec![String::from("a.js"), String::from("b.js")],
+        vec![String::from("foo")],
+    )
+    .unwrap();
+
+    let mut expected = SourceMap::new("/");
+    let sources = expected.add_sources(vec!["a.js", "b.js"]);
+    let name = expected.add_name("foo");
+    expected.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, sources[0], None)));
+    expected.add_mapping(
+        0,
+        6,
+        Some(OriginalLocation::new(1, 2, sources[1], Some(name))),
+    );
+    expected.add_mapping(1, 0, None);
+
+    let mut vlq: Vec<u8> = vec![];
+    let mut expected_vlq: Vec<u8> = vec![];
+    let mut sourcemap = sourcemap;
+    sourcemap.write_vlq(&mut vlq).unwrap();
+    expected.write_vlq(&mut expected_vlq).unwrap();
+    assert_eq!(vlq, expected_vlq);
+    assert_eq!(sourcemap.get_sources(), expected.get_sources());
+    assert_eq!(sourcemap.get_names(), expected.get_names());
+
+    assert!(SourceMap::from_indexed_mappings("/", &[0, 0, 0, 0, 3, -1], vec![], vec![]).is_err());
+}
+
+#[test]
+fn test_add_vlq_map_sources_content_length() {
+    let mut map = SourceMap::new("/");
+    map.add_vlq_map(b"AAAA", vec!["a.js", "b.js"], vec!["let a;"], vec![], 0, 0)
+        .unwrap();
+    assert_eq!(map.get_sources_content(), &vec!["let a;"]);
+
+    let mut map = SourceMap::new("/");
+    map.add_vlq_map(
+        b"AAAA",
+        vec!["a.js"],
+        vec!["let a;", "let b;"],
+        vec![],
+        0,
+        0,
+    )
+    .unwrap();
+    assert_eq!(map.get_sources_content(), &vec!["let a;"]);
+
+    let mut map = SourceMap::new("/");
+    let z = map.add_source("z.js");
+    map.add_source("a.js");
+    map.set_source_content(z as usize, "let z;").unwrap();
+    map.set_source_content(1, "let a;").unwrap();
+    map.add_vlq_map(
+        b"AAAA",
+        vec!["a.js", "b.js"],
+        vec!["", "let b;"],
+        vec![],
+        0,
+        0,
+    )
+    .unwrap();
+    assert_eq!(
+        map.get_sources_content(),
+        &vec!["let z;", "let a;", "let b;"]
+    );
+}
+
+#[test]
+fn test_write_vlq_from() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(100, 0, None);
+    map.add_mapping(101, 4, None);
+
+    let mut vlq: Vec<u8> = vec![];
+    map.write_vlq_from(&mut vlq, 100).unwrap();
+    assert_eq!(&vlq[..], b"A;I");
+}
+
+#[test]
+fn test_dedupe_sources() {
+    let mut map = SourceMap::new("/");
+    map.inner.sources = vec![
+        String::from("a.js"),
+        String::from("b.js"),
+        String::from("a.js"),
+    ];
+    map.inner.sources_content = vec![String::from(""), String::from("b"), String::from("a")];
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 0, None)));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(1, 0, 1, None)));
+    map.add_mapping(0, 8, Some(OriginalLocation::new(2, 0, 2, None)));
+
+    map.dedupe_sources().unwrap();
+    assert_eq!(
+        map.get_sources(),
+        &vec![String::from("a.js"), String::from("b.js")]
+    );
+    assert_eq!(
+        map.get_sources_content(),
+        &vec![String::from("a"), String::from("b")]
+    );
+    let sources: Vec<u32> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.original.unwrap().source)
+        .collect();
+    assert_eq!(sources, vec![0, 1, 0]);
+}
+
+#[test]
+fn test_offset_columns_count() {
+    let mut map = SourceMap::new("/");
+    for column in [0, 10, 20, 30].iter() {
+        map.add_mapping(0, *column, None);
+    }
+
+    assert_eq!(map.offset_columns(0, 15, 5).unwrap(), 2);
+    assert_eq!(map.offset_columns(0, 100, 5).unwrap(), 0);
+    assert_eq!(map.offset_columns(5, 0, 5).unwrap(), 0);
+}
+
+#[test]
+fn test_find_closest_original_mapping() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(3, 2, 0, None)));
+    map.add_mapping(0, 5, None);
+    map.add_mapping(0, 10, Some(OriginalLocation::new(4, 0, 0, None)));
+
+    assert!(map.find_closest_mapping(0, 7).unwrap().original.is_none());
+    let mapping = map.find_closest_original_mapping(0, 7).unwrap();
+    assert_eq!(mapping.generated_column, 0);
+    assert_eq!(mapping.original.unwrap().original_line, 3);
+    let mapping = map.find_closest_original_mapping(0, 10).unwrap();
+    assert_eq!(mapping.original.unwrap().original_line, 4);
+    assert!(map.find_closest_original_mapping(1, 0).is_none());
+}
+
+#[test]
+fn test_into_parts() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let name = map.add_name("foo");
+    map.set_source_content(source as usize, "foo()").unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+    map.add_mapping(2, 4, None);
+    let mut expected_vlq: Vec<u8> = vec![];
+    map.write_vlq(&mut expected_vlq).unwrap();
+
+    let (sources, sources_content, names, mapping_lines) = map.into_parts();
+    let mut map =
+        SourceMap::from_parts("/", sources, sources_content, names, mapping_lines).unwrap();
+    let mut vlq: Vec<u8> = vec![];
+    map.write_vlq(&mut vlq).unwrap();
+    assert_eq!(vlq, expected_vlq);
+    assert_eq!(map.get_sources(), &vec![String::from("a.js")]);
+    assert_eq!(map.get_source_content(0).unwrap(), "foo()");
+    assert_eq!(map.get_names(), &vec![String::from("foo")]);
+}
+
+#[test]
+fn test_from_parts_validation() {
+    let mut line = MappingLine::new();
+    line.add_mapping(0, Some(OriginalLocation::new(0, 0, 1, None)));
+    let map = SourceMap::from_parts(
+        "/",
+        vec![String::from("a.js"), String::from("b.js")],
+        vec![String::from("a"), String::from("b")],
+        vec![],
+        vec![line.clone()],
+    )
+    .unwrap();
+    assert_eq!(map.get_mappings()[0].original.unwrap().source, 1);
+
+    let error = SourceMap::from_parts(
+        "/",
+        vec![String::from("a.js")],
+        vec![],
+        vec![],
+        vec![line.clone()],
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::SourceOutOfRange
+    ));
+
+    let mut named_line = MappingLine::new();
+    named_line.add_mapping(0, Some(OriginalLocation::new(0, 0, 0, Some(0))));
+    let error = SourceMap::from_parts(
+        "/",
+        vec![String::from("a.js")],
+        vec![],
+        vec![],
+        vec![named_line],
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::NameOutOfRange
+    ));
+
+    let error = SourceMap::from_parts(
+        "/",
+        vec![String::from("a.js")],
+        vec![String::from("a"), String::from("b")],
+        vec![],
+        vec![],
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::SourceOutOfRange
+    ));
+}
+
+#[test]
+fn test_max_original_line() {
+    let mut map = SourceMap::new("/");
+    for line in 0..=10 {
+        map.add_mapping(line, 0, Some(OriginalLocation::new(10 - line, 0, 0, None)));
+    }
+    map.add_mapping(11, 0, Some(OriginalLocation::new(20, 0, 1, None)));
+
+    assert_eq!(map.max_original_line(0), Some(10));
+    assert_eq!(map.max_original_line(1), Some(20));
+    assert_eq!(map.max_original_line(2), None);
+}
+
+#[test]
+fn test_warnings() {
+    let mut map = SourceMap::new("/");
+    assert!(map.warnings().is_empty());
+
+    map.add_source("a.js");
+    let warnings = map.warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(matches!(
+        warnings[0].error_type,
+        SourceMapErrorType::NoMappings
+    ));
+
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 0, None)));
+    assert!(map.warnings().is_empty());
+}
+
+#[test]
+fn test_retain_sources() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("node_modules/lib/index.js");
+    let b = map.add_source("src/index.js");
+    map.set_source_content(a as usize, "lib").unwrap();
+    map.set_source_content(b as usize, "index").unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(1, 0, b, None)));
+
+    map.retain_sources(|source| !source.contains("node_modules"))
+        .unwrap();
+    assert_eq!(map.get_sources(), &vec![String::from("src/index.js")]);
+    assert_eq!(map.get_sources_content(), &vec![String::from("index")]);
+    let mappings = map.get_mappings();
+    assert!(mappings[0].original.is_none());
+    assert_eq!(mappings[1].original.unwrap().source, 0);
+    assert_eq!(mappings[1].original.unwrap().original_line, 1);
+}
+
+#[test]
+fn test_resolve_batch() {
+    let mut map = SourceMap::new("/");
+    for line in 0..5 {
+        for column in [0, 8, 16].iter() {
+            map.add_mapping(
+                line,
+                *column,
+                Some(OriginalLocation::new(line + 10, *column + 1, 0, None)),
+            );
+        }
+    }
+
+    let queries = [
+        (3, 9),
+        (0, 0),
+        (4, 20),
+        (9, 0),
+        (1, 15),
+        (1, 3),
+        (1, 16),
+        (1, 8),
+        (1, 100),
+    ];
+    let results = map.resolve_batch(&queries);
+    assert_eq!(results.len(), queries.len());
+    for (query, result) in queries.iter().zip(results.iter()) {
+        let expected = map.find_closest_mapping(query.0, query.1);
+        assert_eq!(format!("{:?}", result), format!("{:?}", expected));
+    }
+}
+
+#[test]
+fn test_apply_position_deltas() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 0, None)));
+    map.add_mapping(0, 10, Some(OriginalLocation::new(1, 0, 0, None)));
+    map.add_mapping(1, 5, Some(OriginalLocation::new(2, 0, 0, None)));
+    map.add_mapping(1, 20, Some(OriginalLocation::new(3, 0, 0, None)));
+
+    let mut deltas: BTreeMap<(u32, u32), (i64, i64)> = BTreeMap::new();
+    // moves onto the mapping at 1:5, which gets replaced
+    deltas.insert((0, 10), (1, -5));
+    deltas.insert((1, 20), (1, 2));
+    map.apply_position_deltas(&deltas).unwrap();
+
+    let positions: Vec<(u32, u32, u32)> = map
+        .get_mappings()
+        .iter()
+        .map(|m| {
+            (
+                m.generated_line,
+                m.generated_column,
+                m.original.unwrap().original_line,
+            )
+        })
+        .collect();
+    assert_eq!(positions, vec![(0, 0, 0), (1, 5, 1), (2, 22, 3)]);
+
+    // Nothing changes when any delta is invalid, even if the valid ones come first
+    let mut deltas: BTreeMap<(u32, u32), (i64, i64)> = BTreeMap::new();
+    deltas.insert((0, 0), (0, 3));
+    deltas.insert((1, 5), (-2, 0));
+    let err = map.apply_position_deltas(&deltas).unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        SourceMapErrorType::UnexpectedNegativeNumber
+    ));
+    assert_eq!(
+        map.get_mappings()
+            .iter()
+            .map(|m| (m.generated_line, m.generated_column))
+            .collect::<Vec<_>>(),
+        vec![(0, 0), (1, 5), (2, 22)]
+    );
+
+    // Two mappings moved onto the same position, the later entry in the table wins
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 9, Some(OriginalLocation::new(1, 0, 0, None)));
+    map.add_mapping(0, 3, Some(OriginalLocation::new(0, 0, 0, None)));
+    let mut deltas: BTreeMap<(u32, u32), (i64, i64)> = BTreeMap::new();
+    deltas.insert((0, 3), (1, 1));
+    deltas.insert((0, 9), (1, -5));
+    map.apply_position_deltas(&deltas).unwrap();
+    let mappings = map.get_mappings();
+    assert_eq!(mappings.len(), 1);
+    assert_eq!(
+        (mappings[0].generated_line, mappings[0].generated_column),
+        (1, 4)
+    );
+    assert_eq!(mappings[0].original.unwrap().original_line, 1);
+}
+
+#[test]
+fn test_assert_roundtrip() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let name = map.add_name("foo");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+    map.add_mapping(0, 12, None);
+    map.add_mapping(3, 4, Some(OriginalLocation::new(7, 2, source, None)));
+    map.assert_roundtrip().unwrap();
+
+    // Duplicate sources get deduplicated when parsing, so the mapping ends up pointing to another source index
+    map.inner.sources.push(String::from("a.js"));
+    map.add_mapping(4, 0, Some(OriginalLocation::new(0, 0, 1, None)));
+    let error = map.assert_roundtrip().unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::InvalidMapping
+    ));
+}
+
+#[test]
+fn test_append_sourcemap_other_project_root() {
+    let mut map = SourceMap::new("/project");
+    let source = map.add_source("/project/src/index.js");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, None)));
+
+    let mut other = SourceMap::new("/project/packages/lib");
+    let source = other.add_source("/project/packages/lib/src/index.js");
+    other.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, None)));
+    let source = other.add_source("/project/src/index.js");
+    other.add_mapping(1, 0, Some(OriginalLocation::new(0, 0, source, None)));
+    assert_eq!(
+        other.get_sources(),
+        &vec![
+            String::from("src/index.js"),
+            String::from("../../src/index.js")
+        ]
+    );
+
+    map.append_sourcemap(&mut other, None).unwrap();
+    assert_eq!(
+        map.get_sources(),
+        &vec![
+            String::from("src/index.js"),
+            String::from("packages/lib/src/index.js")
+        ]
+    );
+    let sources: Vec<u32> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.original.unwrap().source)
+        .collect();
+    assert_eq!(sources, vec![0, 1, 0]);
+}
+
+#[test]
+fn test_clear_keeps_capacity() {
+    let mut map = SourceMap::new("/");
+    assert_eq!(map.sources_capacity(), 0);
+    assert_eq!(map.names_capacity(), 0);
+
+    for i in 0..10 {
+        let source = map.add_source(format!("{}.js", i).as_str());
+        let name = map.add_name(format!("name{}", i).as_str());
+        map.add_mapping(i, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+    }
+    let sources_capacity = map.sources_capacity();
+    let names_capacity = map.names_capacity();
+    assert!(sources_capacity >= 10);
+    assert!(names_capacity >= 10);
+
+    map.clear();
+    assert!(map.get_sources().is_empty());
+    assert!(map.get_names().is_empty());
+    assert!(map.get_mappings().is_empty());
+    assert_eq!(map.sources_capacity(), sources_capacity);
+    assert_eq!(map.names_capacity(), names_capacity);
+}
+
+#[test]
+fn test_offset_lines_at() {
+    let create_map = || {
+        let mut map = SourceMap::new("/");
+        for line in 0..4 {
+            map.add_mapping(line, 0, Some(OriginalLocation::new(line, 0, 0, None)));
+        }
+        map
+    };
+    let generated_lines = |map: &SourceMap| -> Vec<(u32, u32)> {
+        map.get_mappings()
+            .iter()
+            .map(|m| (m.original.unwrap().original_line, m.generated_line))
+            .collect()
+    };
+
+    let mut map = create_map();
+    map.offset_lines_at(1, 2, true).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (1, 3), (2, 4), (3, 5)]);
+
+    let mut map = create_map();
+    map.offset_lines_at(1, 2, false).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (1, 1), (2, 4), (3, 5)]);
+
+    let mut map = create_map();
+    map.offset_lines_at(2, -1, true).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (2, 1), (3, 2)]);
+
+    let mut map = create_map();
+    map.offset_lines_at(2, -1, false).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (1, 1), (3, 2)]);
+
+    // A negative offset removes generated_line first and then the lines before it
+    let mut map = create_map();
+    map.offset_lines_at(2, -2, false).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (3, 1)]);
+
+    let mut map = create_map();
+    map.offset_lines_at(3, -1, false).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (1, 1), (2, 2)]);
+}
+
+#[test]
+fn test_coalesce() {
+    let mut map = SourceMap::new("/");
+    map.name_dedup = NameDedup::None;
+    for _ in 0..3 {
+        let mut chunk = SourceMap::new("/");
+        let source = chunk.add_source("a.js");
+        let name = chunk.add_name("foo");
+        chunk.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+        map.append_sourcemap(&mut chunk, None).unwrap();
+    }
+    map.inner.sources.push(String::from("a.js"));
+    map.add_mapping(3, 0, Some(OriginalLocation::new(0, 0, 1, None)));
+    assert_eq!(map.get_names().len(), 3);
+    assert_eq!(map.get_sources().len(), 2);
+
+    map.coalesce().unwrap();
+    assert_eq!(map.get_sources(), &vec![String::from("a.js")]);
+    assert_eq!(map.get_names(), &vec![String::from("foo")]);
+    for mapping in map.get_mappings() {
+        let original = mapping.original.unwrap();
+        assert_eq!(original.source, 0);
+        assert!(matches!(original.name, None | Some(0)));
+    }
+}
+
+#[test]
+fn test_mapping_ranges() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 0, None)));
+    map.add_mapping(0, 10, Some(OriginalLocation::new(0, 6, 0, None)));
+    map.add_mapping(0, 20, Some(OriginalLocation::new(1, 2, 0, None)));
+    map.add_mapping(0, 30, Some(OriginalLocation::new(0, 0, 1, None)));
+
+    let range = map.mapping_ranges(0, 15).unwrap();
+    assert_eq!(range.generated_start_column, 10);
+    assert_eq!(range.generated_end_column, Some(20));
+    assert_eq!(
+        range.original_start,
+        Some(OriginalLocation::new(0, 6, 0, None))
+    );
+    assert_eq!(
+        range.original_end,
+        Some(OriginalLocation::new(1, 2, 0, None))
+    );
+
+    // The next mapping points into another source
+    let range = map.mapping_ranges(0, 25).unwrap();
+    assert_eq!(range.generated_end_column, Some(30));
+    assert_eq!(range.original_end, None);
+
+    let range = map.mapping_ranges(0, 35).unwrap();
+    assert_eq!(range.generated_start_column, 30);
+    assert_eq!(range.generated_end_column, None);
+    assert_eq!(range.original_end, None);
+    assert!(map.mapping_ranges(1, 0).is_none());
+}
+
+#[test]
+fn test_set_mapping_name() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 0, None)));
+    map.add_mapping(0, 5, None);
+
+    map.set_mapping_name(0, 0, Some("foo")).unwrap();
+    assert_eq!(map.get_mappings()[0].original.unwrap().name, Some(0));
+
+    map.set_mapping_name(0, 0, Some("bar")).unwrap();
+    assert_eq!(map.get_mappings()[0].original.unwrap().name, Some(1));
+    assert_eq!(map.get_name(1).unwrap(), "bar");
+
+    map.set_mapping_name(0, 0, None).unwrap();
+    assert_eq!(map.get_mappings()[0].original.unwrap().name, None);
+
+    assert!(map.set_mapping_name(0, 5, Some("foo")).is_err());
+    assert!(map.set_mapping_name(0, 3, Some("foo")).is_err());
+    assert!(map.set_mapping_name(4, 0, Some("foo")).is_err());
+    assert_eq!(map.get_names().len(), 2);
+}
+
+#[test]
+fn test_sources_content_byte_size() {
+    let mut map = SourceMap::new("/");
+    assert_eq!(map.sources_content_byte_size(), 0);
+
+    map.add_sources(vec!["a.js", "b.js", "c.js"]);
+    map.set_source_content(0, "abc").unwrap();
+    map.set_source_content(2, "h\u{e9}").unwrap();
+    assert_eq!(map.sources_content_byte_size(), 6);
+}
+
+#[test]
+fn test_debug_id() {
+    let mut map = SourceMap::new("/");
+    assert_eq!(map.get_debug_id(), None);
+
+    map.set_debug_id(Some("85314830-023f-4cf1-a267-535f4e37bb17"));
+    let mut buffer = AlignedVec::new();
+    map.to_buffer(&mut buffer).unwrap();
+    let mut map = SourceMap::from_buffer("/", &buffer).unwrap();
+    assert_eq!(
+        map.get_debug_id(),
+        Some("85314830-023f-4cf1-a267-535f4e37bb17")
+    );
+
+    map.set_debug_id(None);
+    assert_eq!(map.get_debug_id(), None);
+}
+
+#[test]
+fn test_remove_dangling_mappings() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let name = map.add_name("foo");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(0, 4, 3, None)));
+    map.add_mapping(0, 8, Some(OriginalLocation::new(0, 8, source, Some(5))));
+    map.add_mapping(0, 12, None);
+
+    assert_eq!(map.remove_dangling_mappings(), 2);
+    let mappings = map.get_mappings();
+    assert_eq!(mappings[0].original.unwrap().name, Some(name));
+    assert!(mappings[1].original.is_none());
+    assert_eq!(mappings[2].original.unwrap().name, None);
+    assert_eq!(mappings[2].original.unwrap().original_column, 8);
+    assert_eq!(map.remove_dangling_mappings(), 0);
+}
+
+#[test]
+fn test_try_add_mapping() {
+    let mut map = SourceMap::new("/");
+    let error = map.try_add_mapping(u32::MAX, 0, None).unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::UnexpectedlyBigNumber
+    ));
+    assert!(map.try_add_mapping(0, u32::MAX, None).is_err());
+    assert!(map.get_mappings().is_empty());
+
+    map.try_add_mapping(2, 4, None).unwrap();
+    assert_eq!(map.get_mappings().len(), 1);
+}
+
+#[test]
+fn test_lines() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, None);
+    map.add_mapping(0, 4, None);
+    map.add_mapping(2, 1, None);
+
+    let counts: Vec<(u32, usize)> = map
+        .lines()
+        .map(|(generated_line, line)| (generated_line, line.columns().count()))
+        .collect();
+    assert_eq!(counts, vec![(0, 2), (1, 0), (2, 1)]);
+}
+
+#[test]
+fn test_normalize_content_line_endings() {
+    let mut map = SourceMap::new("/");
+    map.add_sources(vec!["a.js", "b.js"]);
+    map.set_source_content(0, "a;\r\nb;\r\n").unwrap();
+    map.set_source_content(1, "c;\nd;").unwrap();
+
+    map.normalize_content_line_endings();
+    assert_eq!(map.get_source_content(0).unwrap(), "a;\nb;\n");
+    assert_eq!(map.get_source_content(1).unwrap(), "c;\nd;");
+}
+
+#[test]
+fn test_get_source_and_name() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let name = map.add_name("foo");
+
+    assert_eq!(map.get_source(source).unwrap(), "a.js");
+    assert_eq!(map.get_name(name).unwrap(), "foo");
+    assert!(matches!(
+        map.get_source(1).unwrap_err().error_type,
+        SourceMapErrorType::SourceOutOfRange
+    ));
+    assert!(matches!(
+        map.get_name(1).unwrap_err().error_type,
+        SourceMapErrorType::NameOutOfRange
+    ));
+}
+
+#[test]
+fn test_generated_extent() {
+    let mut map = SourceMap::new("/");
+    assert_eq!(map.generated_extent(), (0, 0));
+
+    map.add_mapping(0, 30, None);
+    map.add_mapping(3, 12, None);
+    map.add_mapping(3, 4, None);
+    assert_eq!(map.generated_extent(), (3, 12));
+
+    // trailing lines without mappings don't count
+    map.offset_lines(4, 2).unwrap();
+    assert_eq!(map.stats().lines, 6);
+    assert_eq!(map.generated_extent(), (3, 12));
+}
+
+#[test]
+fn test_clone_into() {
+    let mut map = SourceMap::new("/root");
+    let source = map.add_source("/root/a.js");
+    let name = map.add_name("foo");
+    map.set_source_content(source as usize, "foo()").unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+    map.add_mapping(1, 4, None);
+
+    let mut dest = SourceMap::new("/");
+    for i in 0..16 {
+        dest.add_source(format!("{}.js", i).as_str());
+        dest.add_name(format!("name{}", i).as_str());
+    }
+    let sources_capacity = dest.sources_capacity();
+    let names_capacity = dest.names_capacity();
+
+    map.clone_into(&mut dest);
+    assert_eq!(dest.project_root, "/root");
+    assert_eq!(dest.get_sources(), map.get_sources());
+    assert_eq!(dest.get_sources_content(), map.get_sources_content());
+    assert_eq!(dest.get_names(), map.get_names());
+    assert_eq!(dest.get_mappings(), map.get_mappings());
+    assert_eq!(dest.sources_capacity(), sources_capacity);
+    assert_eq!(dest.names_capacity(), names_capacity);
+}
+
+#[test]
+fn test_append_sourcemap_preserves_names() {
+    for name_dedup in [NameDedup::Exact, NameDedup::None].iter() {
+        let mut map = SourceMap::new("/");
+        map.name_dedup = *name_dedup;
+        let source = map.add_source("a.js");
+        for (column, name) in ["foo", "bar"].iter().enumerate() {
+            let name = map.add_name(name);
+            map.add_mapping(
+                0,
+                column as u32,
+                Some(OriginalLocation::new(0, 0, source, Some(name))),
+            );
+        }
+
+        let mut other = SourceMap::new("/");
+        let source = other.add_source("b.js");
+        for (column, name) in ["bar", "baz", "foo"].iter().enumerate() {
+            let name = other.add_name(name);
+            other.add_mapping(
+                0,
+                column as u32,
+                Some(OriginalLocation::new(0, 0, source, Some(name))),
+            );
+        }
+
+        map.append_sourcemap(&mut other, None).unwrap();
+        let names: Vec<&str> = map
+            .get_mappings()
+            .iter()
+            .map(|m| map.get_name(m.original.unwrap().name.unwrap()).unwrap())
+            .collect();
+        assert_eq!(names, vec!["foo", "bar", "bar", "baz", "foo"]);
+    }
+}
+
+#[test]
+fn test_zero_offsets() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 10, Some(OriginalLocation::new(0, 0, 0, None)));
+    map.add_mapping(0, 0, None);
+    map.add_mapping(2, 5, Some(OriginalLocation::new(1, 0, 0, None)));
+    let mut expected_vlq: Vec<u8> = vec![];
+    map.write_vlq(&mut expected_vlq).unwrap();
+
+    assert_eq!(map.offset_columns(0, 0, 0).unwrap(), 0);
+    map.offset_lines(1, 0).unwrap();
+    let mut vlq: Vec<u8> = vec![];
+    map.write_vlq(&mut vlq).unwrap();
+    assert_eq!(vlq, expected_vlq);
+}
+
+#[test]
+fn test_set_sources_content() {
+    let mut map = SourceMap::new("/");
+    map.add_sources(vec!["a.js", "b.js", "c.js"]);
+    map.set_source_content(1, "old").unwrap();
+
+    map.set_sources_content(vec![Some(String::from("a")), None, Some(String::from("c"))])
+        .unwrap();
+    assert_eq!(map.get_source_content(0).unwrap(), "a");
+    assert_eq!(map.get_source_content(1).unwrap(), "old");
+    assert_eq!(map.get_source_content(2).unwrap(), "c");
+
+    let mut map = SourceMap::new("/");
+    map.add_sources(vec!["a.js", "b.js", "c.js"]);
+    map.set_sources_content(vec![None, None, Some(String::from("c"))])
+        .unwrap();
+    assert_eq!(map.get_source_content(1).unwrap(), "");
+    assert_eq!(map.get_source_content(2).unwrap(), "c");
+
+    assert!(map
+        .set_sources_content(vec![None, None, None, Some(String::from("d"))])
+        .is_err());
+}
+
+#[test]
+fn test_find_closest_mapping_line_boundaries() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 4, Some(OriginalLocation::new(1, 0, 0, None)));
+    map.add_mapping(0, 10, Some(OriginalLocation::new(2, 0, 0, None)));
+
+    // Before the first mapping of the line
+    let mapping = map.find_closest_mapping(0, 0).unwrap();
+    assert_eq!(mapping.generated_column, 4);
+    assert_eq!(mapping.original.unwrap().original_line, 1);
+
+    // After the last mapping of the line
+    let mapping = map.find_closest_mapping(0, 50).unwrap();
+    assert_eq!(mapping.generated_column, 10);
+    assert_eq!(mapping.original.unwrap().original_line, 2);
+}
+
+#[test]
+fn test_append_sourcemap_stable_sources_order() {
+    let concat = || {
+        let mut map = SourceMap::new("/");
+        for sources in [
+            vec!["c.js", "a.js"],
+            vec!["b.js", "a.js"],
+            vec!["d.js", "c.js", "e.js"],
+        ]
+        .iter()
+        {
+            let mut chunk = SourceMap::new("/");
+            for (line, source) in sources.iter().enumerate() {
+                let source = chunk.add_source(source);
+                chunk.add_mapping(
+                    line as u32,
+                    0,
+                    Some(OriginalLocation::new(0, 0, source, None)),
+                );
             }
+            map.append_sourcemap(&mut chunk, None).unwrap();
         }
 
-        Ok(())
+        let mut vlq: Vec<u8> = vec![];
+        map.write_vlq(&mut vlq).unwrap();
+        (map.get_sources().clone(), vlq)
+    };
+
+    let (sources, vlq) = concat();
+    assert_eq!(sources, vec!["c.js", "a.js", "b.js", "d.js", "e.js"]);
+    assert_eq!(concat(), (sources, vlq));
+}
+
+#[test]
+fn test_get_mappings_sorted() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(1, 8, None);
+    map.add_mapping(0, 20, None);
+    map.add_mapping(1, 2, None);
+    map.add_mapping(0, 4, None);
+
+    let positions: Vec<(u32, u32)> = map
+        .get_mappings()
+        .iter()
+        .map(|m| (m.generated_line, m.generated_column))
+        .collect();
+    assert_eq!(positions, vec![(0, 4), (0, 20), (1, 2), (1, 8)]);
+}
+
+#[test]
+fn test_find_closest_mapping_clamped() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 0, None)));
+    map.add_mapping(2, 8, Some(OriginalLocation::new(4, 2, 0, None)));
+    map.add_mapping(2, 3, Some(OriginalLocation::new(4, 0, 0, None)));
+
+    assert!(map.find_closest_mapping(10, 5).is_none());
+    let mapping = map.find_closest_mapping_clamped(10, 5).unwrap();
+    assert_eq!((mapping.generated_line, mapping.generated_column), (2, 8));
+
+    // Lines within the sourcemap behave the same as find_closest_mapping
+    assert!(map.find_closest_mapping_clamped(1, 0).is_none());
+    let mapping = map.find_closest_mapping_clamped(2, 4).unwrap();
+    assert_eq!(mapping.generated_column, 3);
+
+    assert!(SourceMap::new("/")
+        .find_closest_mapping_clamped(0, 0)
+        .is_none());
+}
+
+#[test]
+fn test_mappings_by_source() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    let b = map.add_source("b.js");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(0, 0, b, None)));
+    map.add_mapping(0, 8, None);
+    map.add_mapping(1, 0, Some(OriginalLocation::new(1, 0, a, None)));
+
+    let mappings_by_source = map.mappings_by_source();
+    assert_eq!(mappings_by_source.len(), 2);
+    let positions = |source: u32| -> Vec<(u32, u32)> {
+        mappings_by_source[&source]
+            .iter()
+            .map(|m| (m.generated_line, m.generated_column))
+            .collect()
+    };
+    assert_eq!(positions(a), vec![(0, 0), (1, 0)]);
+    assert_eq!(positions(b), vec![(0, 4)]);
+}
+
+#[test]
+fn test_buffer_roundtrip() {
+    let mut map = SourceMap::new("/project");
+    let a = map.add_source("/project/a.js");
+    map.add_source("/project/b.js");
+    let c = map.add_source("/project/c.js");
+    map.set_source_content(c as usize, "c()").unwrap();
+    let name = map.add_name("foo");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, Some(name))));
+    map.add_mapping(0, 6, Some(OriginalLocation::new(0, 4, a, None)));
+    map.add_mapping(0, 3, None);
+    map.add_mapping(3, 1, Some(OriginalLocation::new(2, 1, c, None)));
+    map.set_debug_id(Some("85314830-023f-4cf1-a267-535f4e37bb17"));
+
+    let mut buffer = AlignedVec::new();
+    map.to_buffer(&mut buffer).unwrap();
+    let mut copy = SourceMap::from_buffer("/project", &buffer).unwrap();
+
+    assert_eq!(copy.project_root, map.project_root);
+    assert_eq!(copy.get_sources(), map.get_sources());
+    assert_eq!(
+        copy.get_sources_content(),
+        &vec![String::new(), String::new(), String::from("c()")]
+    );
+    assert_eq!(copy.get_names(), map.get_names());
+    assert_eq!(copy.get_mappings(), map.get_mappings());
+    assert_eq!(copy.get_debug_id(), map.get_debug_id());
+    assert_eq!(copy.stats(), map.stats());
+
+    let mut vlq: Vec<u8> = vec![];
+    map.write_vlq(&mut vlq).unwrap();
+    let mut copy_vlq: Vec<u8> = vec![];
+    copy.write_vlq(&mut copy_vlq).unwrap();
+    assert_eq!(copy_vlq, vlq);
+}
+
+#[test]
+fn test_offset_lines_negative_overlap() {
+    let create_map = || {
+        let mut map = SourceMap::new("/");
+        for line in 0..5 {
+            map.add_mapping(line, 0, Some(OriginalLocation::new(line, 0, 0, None)));
+        }
+        map
+    };
+    let generated_lines = |map: &SourceMap| -> Vec<(u32, u32)> {
+        map.get_mappings()
+            .iter()
+            .map(|m| (m.original.unwrap().original_line, m.generated_line))
+            .collect()
+    };
+
+    // Lines 3 and 4 move back onto lines 1 and 2, which get overwritten
+    let mut map = create_map();
+    map.offset_lines(3, -2).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (3, 1), (4, 2)]);
+
+    // Pivot past the last line, only the existing lines in the overwritten range are removed
+    let mut map = create_map();
+    map.offset_lines(7, -3).unwrap();
+    assert_eq!(generated_lines(&map), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+
+    // Shifting before line 0
+    let mut map = create_map();
+    let error = map.offset_lines(1, -2).unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::UnexpectedNegativeNumber
+    ));
+    assert_eq!(generated_lines(&map).len(), 5);
+}
+
+#[test]
+fn test_contains_source_and_name() {
+    let mut map = SourceMap::new("/project");
+    map.add_source("/project/a.js");
+    map.add_name("foo");
+
+    assert!(map.contains_source("a.js"));
+    assert!(map.contains_source("/project/a.js"));
+    assert!(!map.contains_source("b.js"));
+    assert!(map.contains_name("foo"));
+    assert!(!map.contains_name("bar"));
+    assert_eq!(map.get_sources().len(), 1);
+    assert_eq!(map.get_names().len(), 1);
+}
+
+#[test]
+fn test_offset_original_lines() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    let b = map.add_source("b.js");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(2, 0, b, None)));
+    map.add_mapping(1, 0, Some(OriginalLocation::new(5, 3, a, None)));
+    map.add_mapping(1, 4, None);
+
+    map.offset_original_lines(a, 3).unwrap();
+    let original_lines: Vec<Option<(u32, u32)>> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.original.map(|o| (o.source, o.original_line)))
+        .collect();
+    assert_eq!(
+        original_lines,
+        vec![Some((a, 3)), Some((b, 2)), Some((a, 8)), None]
+    );
+
+    assert!(map.offset_original_lines(b, -3).is_err());
+    assert_eq!(map.get_mappings()[1].original.unwrap().original_line, 2);
+}
+
+#[test]
+fn test_to_indexed_mappings() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let name = map.add_name("foo");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(1, 2, source, Some(name))));
+    map.add_mapping(0, 5, None);
+    map.add_mapping(2, 3, Some(OriginalLocation::new(4, 0, source, None)));
+
+    let mappings_arr = map.to_indexed_mappings();
+    assert_eq!(
+        mappings_arr,
+        vec![0, 0, 1, 2, 0, 0, 0, 5, -1, -1, -1, -1, 2, 3, 4, 0, 0, -1]
+    );
+
+    let copy = SourceMap::from_indexed_mappings(
+        "/",
+        &mappings_arr,
+        map.get_sources().clone(),
+        map.get_names().clone(),
+    )
+    .unwrap();
+    assert_eq!(copy.get_mappings(), map.get_mappings());
+}
+
+#[test]
+fn test_merge_with() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 4, None);
+    map.add_mapping(1, 0, None);
+
+    let mut other = SourceMap::new("/");
+    let b = other.add_source("b.js");
+    other.add_mapping(0, 0, None);
+    other.add_mapping(0, 4, Some(OriginalLocation::new(3, 0, b, None)));
+    other.add_mapping(0, 8, Some(OriginalLocation::new(4, 0, b, None)));
+
+    // Prefer whichever side has an original position
+    map.merge_with(&other, |existing, other| {
+        if existing.original.is_some() {
+            Some(*existing)
+        } else {
+            Some(*other)
+        }
+    })
+    .unwrap();
+
+    let originals: Vec<_> = map
+        .get_mappings()
+        .iter()
+        .map(|m| {
+            (
+                m.generated_line,
+                m.generated_column,
+                m.original.map(|o| (o.source, o.original_line)),
+            )
+        })
+        .collect();
+    assert_eq!(
+        originals,
+        vec![
+            (0, 0, Some((0, 0))),
+            (0, 4, Some((1, 3))),
+            (0, 8, Some((1, 4))),
+            (1, 0, None)
+        ]
+    );
+
+    // A resolver can't move a mapping to another generated position
+    let error = map
+        .merge_with(&other, |existing, _| {
+            Some(Mapping {
+                generated_column: existing.generated_column + 1,
+                ..*existing
+            })
+        })
+        .unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::InvalidMapping
+    ));
+    assert_eq!(
+        error.reason.as_deref(),
+        Some("resolved mapping 0:1 does not match conflicting position 0:0")
+    );
+}
+
+#[test]
+fn test_merge_with_drop_and_errors() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    map.add_mapping(0, 8, Some(OriginalLocation::new(1, 0, a, None)));
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 4, None);
+
+    let mut other = SourceMap::new("/");
+    let b = other.add_source("b.js");
+    let name = other.add_name("b");
+    other.add_mapping(0, 12, Some(OriginalLocation::new(2, 0, b, Some(name))));
+    other.add_mapping(0, 4, Some(OriginalLocation::new(3, 0, b, None)));
+
+    let columns = |map: &SourceMap| -> Vec<u32> {
+        map.get_mappings()
+            .iter()
+            .map(|m| m.generated_column)
+            .collect()
+    };
+
+    // Nothing changes when the resolver moves a mapping or other has invalid indexes
+    let error = map
+        .merge_with(&other, |existing, _| {
+            Some(Mapping {
+                generated_line: existing.generated_line + 1,
+                ..*existing
+            })
+        })
+        .unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::InvalidMapping
+    ));
+    let mut invalid = SourceMap::new("/");
+    invalid.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 3, None)));
+    let error = map
+        .merge_with(&invalid, |_, other| Some(*other))
+        .unwrap_err();
+    assert!(matches!(
+        error.error_type,
+        SourceMapErrorType::SourceOutOfRange
+    ));
+    assert_eq!(map.get_sources(), &vec![String::from("a.js")]);
+    assert!(map.get_names().is_empty());
+    assert_eq!(columns(&map), vec![0, 4, 8]);
+
+    // Returning None removes the mapping at the conflicting position
+    map.merge_with(&other, |_, _| None).unwrap();
+    assert_eq!(columns(&map), vec![0, 8, 12]);
+    assert_eq!(
+        map.find_closest_mapping(0, 12).unwrap().original,
+        Some(OriginalLocation::new(2, 0, 1, Some(0)))
+    );
+
+    // The merged line is sorted, so adding after its last column keeps it sorted
+    map.add_mapping(0, 20, None);
+    let (_, line) = map.lines().next().unwrap();
+    assert!(line.is_sorted);
+    assert_eq!(line.last_column, 20);
+    assert_eq!(columns(&map), vec![0, 8, 12, 20]);
+}
+
+#[test]
+fn test_vlq_writer_chunks() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    let b = map.add_source("b.js");
+    let name = map.add_name("foo");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 10, Some(OriginalLocation::new(0, 6, a, Some(name))));
+    map.add_mapping(2, 4, None);
+    map.add_mapping(3, 2, Some(OriginalLocation::new(5, 1, b, None)));
+    map.add_mapping(5, 8, Some(OriginalLocation::new(2, 3, a, Some(name))));
+    map.add_mapping(5, 0, Some(OriginalLocation::new(1, 0, b, None)));
+
+    let mut expected: Vec<u8> = vec![];
+    map.write_vlq(&mut expected).unwrap();
+
+    let lines: Vec<_> = map.lines().collect();
+    let mut writer = VlqWriter::new();
+    let mut first_chunk: Vec<u8> = vec![];
+    for (generated_line, line) in &lines[..3] {
+        writer
+            .write_line(*generated_line, line, &mut first_chunk)
+            .unwrap();
     }
+    let mut second_chunk: Vec<u8> = vec![];
+    for (generated_line, line) in &lines[3..] {
+        writer
+            .write_line(*generated_line, line, &mut second_chunk)
+            .unwrap();
+    }
+
+    first_chunk.extend(second_chunk);
+    assert_eq!(first_chunk, expected);
+
+    let err = writer.write_line(1, lines[1].1, &mut vec![]).unwrap_err();
+    assert!(matches!(err.error_type, SourceMapErrorType::InvalidMapping));
 }
 
-#[allow(non_fmt_panic)]
 #[test]
-fn test_buffers() {
-    let map = SourceMap::new("/");
-    let mut output = AlignedVec::new();
-    match map.to_buffer(&mut output) {
-        Ok(_) => {}
-        Err(err) => panic!(err),
+fn test_rewrite_source_prefix() {
+    let mut map = SourceMap::new("/");
+    let tmp = map.add_source("build/tmp/a.js");
+    let src = map.add_source("src/a.js");
+    let other = map.add_source("build/tmp/b.js");
+    map.set_source_content(tmp as usize, "").unwrap();
+    map.set_source_content(src as usize, "let a;").unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, tmp, None)));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(1, 0, src, None)));
+    map.add_mapping(0, 8, Some(OriginalLocation::new(2, 0, other, None)));
+
+    assert_eq!(map.rewrite_source_prefix("build/tmp/", "src/").unwrap(), 2);
+    assert_eq!(
+        map.get_sources(),
+        &vec![String::from("src/a.js"), String::from("src/b.js")]
+    );
+    assert_eq!(map.get_sources_content(), &vec![String::from("let a;")]);
+    let sources: Vec<u32> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.original.unwrap().source)
+        .collect();
+    assert_eq!(sources, vec![0, 0, 1]);
+
+    assert_eq!(map.rewrite_source_prefix("build/tmp/", "src/").unwrap(), 0);
+}
+
+#[test]
+fn test_validate_column_alignment() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("emoji.js");
+    map.set_source_content(source as usize, "let a;\nlet b = '\u{1F600}';")
+        .unwrap();
+    // The emoji starts at column 9 and takes up two utf-16 code units
+    map.add_mapping(0, 0, Some(OriginalLocation::new(1, 9, source, None)));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(1, 11, source, None)));
+    map.add_mapping(0, 8, Some(OriginalLocation::new(0, 1, source, None)));
+    assert!(map.validate_column_alignment().is_ok());
+
+    map.add_mapping(0, 12, Some(OriginalLocation::new(1, 10, source, None)));
+    let err = map.validate_column_alignment().unwrap_err();
+    assert!(matches!(err.error_type, SourceMapErrorType::InvalidMapping));
+}
+
+#[test]
+fn test_transaction() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, None)));
+    map.add_mapping(1, 4, Some(OriginalLocation::new(1, 0, source, None)));
+    let before = map.get_mappings();
+
+    let err = map
+        .transaction(|map| {
+            map.add_name("foo");
+            map.offset_lines(0, 2)?;
+            map.offset_columns(2, 0, 10)?;
+            map.offset_lines(3, -10)?;
+            Ok(())
+        })
+        .unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        SourceMapErrorType::UnexpectedNegativeNumber
+    ));
+    assert_eq!(map.get_mappings(), before);
+    assert!(map.get_names().is_empty());
+
+    map.transaction(|map| map.offset_lines(0, 1)).unwrap();
+    assert_eq!(map.get_mappings()[0].generated_line, 1);
+}
+
+#[test]
+fn test_enumerate_sources_and_names() {
+    let mut map = SourceMap::new("/");
+    map.add_sources(vec!["a.js", "b.js"]);
+    map.add_names(vec!["foo", "bar", "baz"]);
+
+    let sources: Vec<_> = map.enumerate_sources().collect();
+    assert_eq!(sources, vec![(0, "a.js"), (1, "b.js")]);
+    let names: Vec<_> = map.enumerate_names().collect();
+    assert_eq!(names, vec![(0, "foo"), (1, "bar"), (2, "baz")]);
+}
+
+#[test]
+fn test_compose_on_missing() {
+    let build = |on_missing: MissingMapping| {
+        let mut map = SourceMap::new("/");
+        let intermediate = map.add_source("intermediate.js");
+        map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, intermediate, None)));
+        map.add_mapping(0, 10, Some(OriginalLocation::new(3, 0, intermediate, None)));
+        map.add_mapping(0, 20, None);
+
+        let mut original_map = SourceMap::new("/");
+        let original = original_map.add_source("original.ts");
+        original_map.add_mapping(0, 0, Some(OriginalLocation::new(5, 2, original, None)));
+
+        map.compose(&mut original_map, ComposeOptions { on_missing })
+            .unwrap();
+        map
+    };
+
+    let map = build(MissingMapping::Unmapped);
+    let mappings = map.get_mappings();
+    assert_eq!(mappings.len(), 3);
+    assert_eq!(
+        mappings[0].original,
+        Some(OriginalLocation::new(5, 2, 1, None))
+    );
+    assert_eq!(mappings[1].original, None);
+    assert_eq!(mappings[2].original, None);
+
+    let map = build(MissingMapping::KeepIntermediate);
+    let mappings = map.get_mappings();
+    assert_eq!(mappings.len(), 3);
+    assert_eq!(
+        mappings[0].original,
+        Some(OriginalLocation::new(5, 2, 1, None))
+    );
+    assert_eq!(
+        mappings[1].original,
+        Some(OriginalLocation::new(3, 0, 0, None))
+    );
+    assert_eq!(map.get_source(0).unwrap(), "intermediate.js");
+
+    let map = build(MissingMapping::Drop);
+    let columns: Vec<_> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.generated_column)
+        .collect();
+    assert_eq!(columns, vec![0, 20]);
+}
+
+#[test]
+fn test_densest_lines() {
+    let mut map = SourceMap::new("/");
+    for (generated_line, count) in [(0, 2), (1, 5), (3, 1), (4, 5), (6, 3)].iter() {
+        for column in 0..*count {
+            map.add_mapping(*generated_line, column, None);
+        }
     }
-    match SourceMap::from_buffer("/", &output) {
-        Ok(map) => {
-            println!("{:?}", map)
+
+    assert_eq!(map.densest_lines(3), vec![(1, 5), (4, 5), (6, 3)]);
+    assert_eq!(map.densest_lines(10).len(), 5);
+    assert!(map.densest_lines(0).is_empty());
+}
+
+#[test]
+fn test_load_source_content() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    let b = map.add_source("b.js");
+    let calls = Arc::new(AtomicUsize::new(0));
+    let loader_calls = calls.clone();
+    map.set_content_loader(move |source| {
+        loader_calls.fetch_add(1, Ordering::SeqCst);
+        if source == "a.js" {
+            Some(String::from("let a;"))
+        } else {
+            None
         }
-        Err(err) => panic!(err),
+    });
+
+    assert_eq!(map.load_source_content(a).unwrap(), "let a;");
+    assert_eq!(map.load_source_content(a).unwrap(), "let a;");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(map.get_source_content(a).unwrap(), "let a;");
+
+    assert_eq!(map.load_source_content(b).unwrap(), "");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(map.load_source_content(5).is_err());
+}
+
+#[test]
+fn test_write_vlq_duplicate_columns() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    map.add_mapping(0, 8, Some(OriginalLocation::new(0, 8, source, None)));
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, None)));
+    map.add_mapping(0, 8, Some(OriginalLocation::new(1, 2, source, None)));
+    map.add_mapping(0, 0, None);
+    map.add_mapping(1, 4, Some(OriginalLocation::new(2, 0, source, None)));
+    map.add_mapping(1, 4, Some(OriginalLocation::new(3, 0, source, None)));
+
+    let mut vlq: Vec<u8> = vec![];
+    map.write_vlq(&mut vlq).unwrap();
+    let mut vlq_again: Vec<u8> = vec![];
+    map.write_vlq(&mut vlq_again).unwrap();
+    assert_eq!(vlq, vlq_again);
+
+    let mut parsed = SourceMap::new("/");
+    parsed
+        .add_vlq_map(&vlq, vec!["a.js"], vec![], vec![], 0, 0)
+        .unwrap();
+    let positions: Vec<_> = parsed
+        .get_mappings()
+        .iter()
+        .map(|m| (m.generated_line, m.generated_column, m.original))
+        .collect();
+    assert_eq!(
+        positions,
+        vec![
+            (0, 0, None),
+            (0, 8, Some(OriginalLocation::new(1, 2, 0, None))),
+            (1, 4, Some(OriginalLocation::new(3, 0, 0, None))),
+        ]
+    );
+}
+
+#[test]
+fn test_append_many_small_sourcemaps() {
+    let mut map = SourceMap::new("/");
+    for i in 0..500 {
+        let mut module_map = SourceMap::new("/");
+        let source = module_map.add_source(&format!("src/module-{}.js", i));
+        let shared = module_map.add_source("src/shared.js");
+        let name = module_map.add_name(&format!("export{}", i % 50));
+        module_map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(name))));
+        module_map.add_mapping(1, 4, Some(OriginalLocation::new(2, 0, shared, None)));
+        map.append_sourcemap(&mut module_map, Some(2)).unwrap();
+    }
+
+    assert_eq!(map.get_sources().len(), 501);
+    assert_eq!(map.get_sources()[1], "src/shared.js");
+    assert_eq!(map.get_names().len(), 50);
+    let mappings = map.get_mappings();
+    assert_eq!(mappings.len(), 1000);
+    let last = mappings[999];
+    assert_eq!(last.generated_line, 999);
+    assert_eq!(last.original, Some(OriginalLocation::new(2, 0, 1, None)));
+    let module = mappings[998].original.unwrap();
+    assert_eq!(map.get_source(module.source).unwrap(), "src/module-499.js");
+    assert_eq!(map.get_name(module.name.unwrap()).unwrap(), "export49");
+}
+
+#[test]
+fn test_append_deduplicated() {
+    let mut list: Vec<String> = (0..100).map(|i| format!("item{}", i)).collect();
+    list.push(String::from("item5"));
+    let items: Vec<String> = vec!["item5", "new", "item99", "new", "other"]
+        .into_iter()
+        .map(String::from)
+        .chain((0..20).map(|i| format!("item{}", i * 5)))
+        .collect();
+
+    let indexes = append_deduplicated(&mut list, items);
+    assert_eq!(&indexes[..5], &[5, 101, 99, 101, 102]);
+    assert_eq!(
+        indexes[5..].to_vec(),
+        (0..20).map(|i| i * 5).collect::<Vec<u32>>()
+    );
+    assert_eq!(list.len(), 103);
+    assert_eq!(list[101], "new");
+    assert_eq!(list[102], "other");
+}
+
+#[test]
+fn test_set_project_root() {
+    let mut map = SourceMap::new("/project/packages/app");
+    map.add_source("src/index.js");
+    map.add_source("../shared/util.js");
+    map.add_source("https://example.com/remote.js");
+
+    map.set_project_root("/project", true).unwrap();
+    assert_eq!(map.project_root, "/project");
+    assert_eq!(
+        map.get_sources(),
+        &vec![
+            String::from("packages/app/src/index.js"),
+            String::from("packages/shared/util.js"),
+            String::from("https://example.com/remote.js"),
+        ]
+    );
+
+    map.set_project_root("/elsewhere", false).unwrap();
+    assert_eq!(map.project_root, "/elsewhere");
+    assert_eq!(map.get_sources()[0], "packages/app/src/index.js");
+
+    let err = map.set_project_root("relative", true).unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        SourceMapErrorType::InvalidFilePath
+    ));
+    assert_eq!(map.project_root, "/elsewhere");
+}
+
+#[test]
+fn test_find_closest_mapping_large_line() {
+    use std::time::{Duration, Instant};
+
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("big.js");
+    // A single line with 25k mappings, every mapping is 4 columns wide and added out of order
+    for i in (0..25_000u32).rev() {
+        map.add_mapping(0, i * 4, Some(OriginalLocation::new(i, 0, source, None)));
+    }
+
+    let start = Instant::now();
+    for i in 0..25_000u32 {
+        let mapping = map.find_closest_mapping(0, i * 4 + 2).unwrap();
+        assert_eq!(mapping.generated_column, i * 4);
+    }
+    // Binary search takes a few milliseconds here, a linear scan takes seconds
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let spot_checks = [
+        (0, 0, 0),
+        (3, 0, 0),
+        (4, 4, 1),
+        (50_001, 50_000, 12_500),
+        (200_000, 99_996, 24_999),
+    ];
+    for (column, generated_column, original_line) in spot_checks.iter() {
+        let mapping = map.find_closest_mapping(0, *column).unwrap();
+        assert_eq!(mapping.generated_column, *generated_column);
+        assert_eq!(mapping.original.unwrap().original_line, *original_line);
     }
+    assert_eq!(map.find_closest_mapping(1, 0), None);
+}
+
+#[test]
+fn test_append_sourcemap_sources_content() {
+    let mut map = SourceMap::new("/");
+    let shared = map.add_source("shared.js");
+    map.set_source_content(shared as usize, "export let shared;")
+        .unwrap();
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, shared, None)));
+
+    let mut with_content = SourceMap::new("/");
+    let module = with_content.add_source("module.js");
+    with_content
+        .set_source_content(module as usize, "let module;")
+        .unwrap();
+    with_content.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, module, None)));
+    map.append_sourcemap(&mut with_content, None).unwrap();
+
+    let mut without_content = SourceMap::new("/");
+    without_content.add_source("other.js");
+    let shared_again = without_content.add_source("shared.js");
+    without_content
+        .set_source_content(shared_again as usize, "")
+        .unwrap();
+    without_content.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, shared_again, None)));
+    map.append_sourcemap(&mut without_content, None).unwrap();
+
+    assert_eq!(
+        map.get_sources(),
+        &vec![
+            String::from("shared.js"),
+            String::from("module.js"),
+            String::from("other.js"),
+        ]
+    );
+    assert_eq!(map.get_source_content(0).unwrap(), "export let shared;");
+    assert_eq!(map.get_source_content(1).unwrap(), "let module;");
+    assert_eq!(map.get_mappings()[2].original.unwrap().source, 0);
+}
+
+#[test]
+fn test_offset_columns_with_underflow() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 0, None);
+    map.add_mapping(0, 3, None);
+    map.add_mapping(0, 10, None);
+
+    let err = map.offset_columns(0, 3, -100).unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        SourceMapErrorType::UnexpectedNegativeNumber
+    ));
+    assert_eq!(map.get_mappings().len(), 3);
+
+    assert_eq!(
+        map.offset_columns_with_underflow(0, 3, -100, ColumnUnderflow::Saturate)
+            .unwrap(),
+        1
+    );
+    let columns: Vec<_> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.generated_column)
+        .collect();
+    assert_eq!(columns, vec![0]);
+
+    map.add_mapping(0, 6, None);
+    assert_eq!(
+        map.offset_columns_with_underflow(0, 0, -100, ColumnUnderflow::Drop)
+            .unwrap(),
+        0
+    );
+    assert!(map.get_mappings().is_empty());
+}
+
+#[test]
+fn test_validate_monotonic_columns() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(0, 10, None);
+    map.add_mapping(0, 5, None);
+    map.add_mapping(1, 0, None);
+    assert!(map.validate().is_ok());
+
+    let mut duplicates = SourceMap::new("/");
+    duplicates.add_mapping(0, 10, None);
+    duplicates.add_mapping(0, 5, None);
+    duplicates.add_mapping(0, 10, None);
+    let err = duplicates.validate().unwrap_err();
+    assert_eq!(
+        err.reason,
+        Some(String::from(
+            "generated position 0:10 comes after column 10"
+        ))
+    );
+
+    let (sources, sources_content, names, mut mapping_lines) = map.into_parts();
+    mapping_lines[0].is_sorted = true;
+    let map = SourceMap::from_parts("/", sources, sources_content, names, mapping_lines).unwrap();
+    let err = map.validate().unwrap_err();
+    assert!(matches!(err.error_type, SourceMapErrorType::InvalidMapping));
+    assert_eq!(
+        err.reason,
+        Some(String::from("generated position 0:5 comes after column 10"))
+    );
+}
+
+#[test]
+fn test_append_sourcemap_extent() {
+    let mut map = SourceMap::new("/");
+    map.add_mapping(9, 4, None);
+
+    let mut other = SourceMap::new("/");
+    other.add_mapping(0, 0, None);
+    other.add_mapping(1, 7, None);
+    other.add_mapping(1, 2, None);
+
+    assert_eq!(map.append_sourcemap(&mut other, None).unwrap(), (11, 7));
+    assert_eq!(map.generated_extent(), (11, 7));
+
+    let mut empty = SourceMap::new("/");
+    assert_eq!(map.append_sourcemap(&mut empty, Some(3)).unwrap(), (11, 7));
+
+    // Trailing unmapped lines of the appended sourcemap don't count
+    let mut other = SourceMap::new("/");
+    other.add_mapping(0, 3, None);
+    other.add_mapping(2, 1, None);
+    other.add_mapping(3, 9, None);
+    other.inner.mapping_lines.push(MappingLine::new());
+    assert_eq!(map.append_sourcemap(&mut other, None).unwrap(), (18, 9));
+    assert_eq!(map.generated_extent(), (18, 9));
+}
+
+#[test]
+fn test_content_diff() {
+    let mut previous = SourceMap::new("/");
+    previous.add_sources(vec!["a.js", "b.js", "c.js"]);
+    previous.set_source_content(0, "let a;").unwrap();
+    previous.set_source_content(1, "let b;").unwrap();
+
+    let mut map = SourceMap::new("/");
+    map.add_sources(vec!["c.js", "b.js", "a.js", "d.js"]);
+    map.set_source_content(1, "let b = 1;").unwrap();
+    map.set_source_content(2, "let a;").unwrap();
+
+    assert_eq!(map.content_diff(&previous), vec![1, 3]);
+    assert!(previous.content_diff(&previous).is_empty());
+}
+
+#[test]
+fn test_add_mapping_if_absent() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let first = Mapping {
+        generated_line: 2,
+        generated_column: 4,
+        original: Some(OriginalLocation::new(0, 0, source, None)),
+    };
+    let second = Mapping {
+        generated_line: 2,
+        generated_column: 4,
+        original: Some(OriginalLocation::new(5, 5, source, None)),
+    };
+
+    assert!(map.add_mapping_if_absent(first));
+    assert!(!map.add_mapping_if_absent(second));
+    assert!(map.add_mapping_if_absent(Mapping {
+        generated_column: 8,
+        ..second
+    }));
+    assert_eq!(
+        map.get_mappings(),
+        vec![
+            first,
+            Mapping {
+                generated_column: 8,
+                ..second
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_canonicalize_source_paths() {
+    let mut map = SourceMap::new("/");
+    map.inner.sources = vec![
+        String::from("src/a.js"),
+        String::from("./src/../src/a.js"),
+        String::from("lib/./b.js"),
+        String::from("https://example.com/x/../c.js"),
+        String::from("lib/b.js"),
+    ];
+    for source in 0..5 {
+        map.add_mapping(
+            0,
+            source * 4,
+            Some(OriginalLocation::new(0, 0, source, None)),
+        );
+    }
+
+    map.canonicalize_source_paths().unwrap();
+    assert_eq!(
+        map.get_sources(),
+        &vec![
+            String::from("src/a.js"),
+            String::from("lib/b.js"),
+            String::from("https://example.com/c.js"),
+        ]
+    );
+    let sources: Vec<u32> = map
+        .get_mappings()
+        .iter()
+        .map(|m| m.original.unwrap().source)
+        .collect();
+    assert_eq!(sources, vec![0, 0, 1, 2, 1]);
+}
+
+#[test]
+fn test_name_usage_counts() {
+    let mut map = SourceMap::new("/");
+    let source = map.add_source("a.js");
+    let hot = map.add_name("hot");
+    let cold = map.add_name("cold");
+    let unused = map.add_name("unused");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, source, Some(hot))));
+    map.add_mapping(0, 4, Some(OriginalLocation::new(0, 4, source, Some(cold))));
+    map.add_mapping(1, 0, Some(OriginalLocation::new(1, 0, source, Some(hot))));
+    map.add_mapping(2, 0, Some(OriginalLocation::new(2, 0, source, None)));
+    map.add_mapping(2, 6, Some(OriginalLocation::new(2, 6, source, Some(hot))));
+    map.add_mapping(3, 0, None);
+
+    let counts = map.name_usage_counts();
+    assert_eq!(counts.len(), 3);
+    assert_eq!(counts[&hot], 3);
+    assert_eq!(counts[&cold], 1);
+    assert_eq!(counts[&unused], 0);
+}
+
+#[test]
+fn test_conflicts_with() {
+    let mut map = SourceMap::new("/");
+    let a = map.add_source("a.js");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a, None)));
+    map.add_mapping(0, 10, Some(OriginalLocation::new(0, 10, a, None)));
+    map.add_mapping(1, 4, Some(OriginalLocation::new(1, 0, a, None)));
+    map.add_mapping(2, 0, None);
+
+    let mut other = SourceMap::new("/");
+    let b = other.add_source("b.js");
+    let a_in_other = other.add_source("a.js");
+    // Same original location, but a different source index
+    other.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, a_in_other, None)));
+    other.add_mapping(0, 10, Some(OriginalLocation::new(0, 0, b, None)));
+    other.add_mapping(0, 20, Some(OriginalLocation::new(0, 20, b, None)));
+    other.add_mapping(2, 0, Some(OriginalLocation::new(2, 0, b, None)));
+    other.add_mapping(1, 4, Some(OriginalLocation::new(1, 0, a_in_other, None)));
+
+    assert_eq!(map.conflicts_with(&other), vec![(0, 10), (2, 0)]);
+    assert_eq!(other.conflicts_with(&map), vec![(0, 10), (2, 0)]);
+    assert!(map.conflicts_with(&map).is_empty());
+}
+
+#[test]
+fn test_from_buffer_rejects_other_layouts() {
+    // The layout of buffers before the header and debug id were added
+    #[derive(Archive, Serialize)]
+    struct LegacySourceMapInner {
+        sources: Vec<String>,
+        sources_content: Vec<String>,
+        names: Vec<String>,
+        mapping_lines: Vec<MappingLine>,
+    }
+
+    let mut map = SourceMap::new("/");
+    map.add_source("a.js");
+    map.add_mapping(0, 0, Some(OriginalLocation::new(0, 0, 0, None)));
+    let (sources, sources_content, names, mapping_lines) = map.into_parts();
+    let mut legacy_buffer = AlignedVec::new();
+    let mut serializer = AlignedSerializer::new(&mut legacy_buffer);
+    serializer
+        .serialize_value(&LegacySourceMapInner {
+            sources,
+            sources_content,
+            names,
+            mapping_lines,
+        })
+        .unwrap();
+
+    let err = SourceMap::from_buffer("/", &legacy_buffer).unwrap_err();
+    assert!(matches!(err.error_type, SourceMapErrorType::BufferError));
+    assert!(BufferedSourceMap::new("/", &legacy_buffer).is_err());
+
+    let mut buffer = AlignedVec::new();
+    SourceMap::new("/").to_buffer(&mut buffer).unwrap();
+    buffer[4] = 2;
+    let err = SourceMap::from_buffer("/", &buffer).unwrap_err();
+    assert!(matches!(err.error_type, SourceMapErrorType::BufferError));
+    assert!(SourceMap::from_buffer("/", &[]).is_err());
+}
+
+#[test]
+fn test_add_sourcemap_repeated_names() {
+    let build_appended = || {
+        let mut appended = SourceMap::new("/");
+        appended.name_dedup = NameDedup::None;
+        let source = appended.add_source("b.js");
+        let names = appended.add_names(vec!["x", "y", "x"]);
+        appended.add_mapping(
+            0,
+            0,
+            Some(OriginalLocation::new(0, 0, source, Some(names[2]))),
+        );
+        appended
+    };
+
+    // The indexes don't depend on whether there are existing names to deduplicate against
+    let mut empty = SourceMap::new("/");
+    empty.add_sourcemap(&mut build_appended(), 0).unwrap();
+    assert_eq!(empty.get_names(), &vec!["x", "y"]);
+    assert_eq!(empty.get_mappings()[0].original.unwrap().name, Some(0));
+
+    let mut existing = SourceMap::new("/");
+    existing.add_name("y");
+    existing.add_sourcemap(&mut build_appended(), 0).unwrap();
+    assert_eq!(existing.get_names(), &vec!["y", "x"]);
+    assert_eq!(existing.get_mappings()[0].original.unwrap().name, Some(1));
+
+    let mut keep_all = SourceMap::new("/");
+    keep_all.name_dedup = NameDedup::None;
+    keep_all.add_sourcemap(&mut build_appended(), 0).unwrap();
+    assert_eq!(keep_all.get_names(), &vec!["x", "y", "x"]);
+    assert_eq!(keep_all.get_mappings()[0].original.unwrap().name, Some(2));
 }
@@ -8,7 +8,17 @@ pub struct LineMapping {
     pub original: Option<OriginalLocation>,
 }
 
-#[derive(Archive, Serialize, Deserialize, Debug, Default)]
+// What offset_columns does with mappings that would end up before column 0.
+// Error fails without changing the line, Saturate moves the mapping that covers column 0 to column 0
+// and removes the others, Drop removes all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnUnderflow {
+    Error,
+    Saturate,
+    Drop,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct MappingLine {
     pub mappings: Vec<LineMapping>,
     pub last_column: u32,
@@ -37,6 +47,11 @@ impl MappingLine {
         self.last_column = generated_column;
     }
 
+    // Iterate over the mappings of this line in insertion order, call ensure_sorted first to get them sorted by column
+    pub fn columns(&self) -> impl Iterator<Item = &LineMapping> {
+        self.mappings.iter()
+    }
+
     pub fn ensure_sorted(&mut self) {
         if !self.is_sorted {
             self.mappings
@@ -45,6 +60,8 @@ impl MappingLine {
         }
     }
 
+    // Find the mapping at or before the column, if the column is before the first mapping of the line
+    // there is nothing before it and the first mapping is returned instead
     pub fn find_closest_mapping(&mut self, generated_column: u32) -> Option<LineMapping> {
         if self.mappings.is_empty() {
             return None;
@@ -56,29 +73,62 @@ impl MappingLine {
             .binary_search_by(|m| m.generated_column.cmp(&generated_column))
         {
             Ok(index) => index,
-            Err(index) => {
-                if index == 0 || index == self.mappings.len() {
-                    return Some(LineMapping {
-                        generated_column: 0,
-                        original: self.mappings[0].original,
-                    });
-                }
-
-                index - 1
-            }
+            Err(0) => 0,
+            Err(index) => index - 1,
         };
 
         Some(self.mappings[index])
     }
 
+    // Find the closest mapping at or before the column that has an original position, skipping unmapped segments
+    pub fn find_closest_original_mapping(&mut self, generated_column: u32) -> Option<LineMapping> {
+        self.ensure_sorted();
+        let end = match self
+            .mappings
+            .binary_search_by(|m| m.generated_column.cmp(&generated_column))
+        {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+
+        self.mappings[..end]
+            .iter()
+            .rev()
+            .find(|m| m.original.is_some())
+            .copied()
+    }
+
+    // Returns the amount of mappings that got shifted, mappings removed by a negative offset are not counted
     pub fn offset_columns(
         &mut self,
         generated_column: u32,
         generated_column_offset: i64,
-    ) -> Result<(), SourceMapError> {
+    ) -> Result<usize, SourceMapError> {
+        self.offset_columns_with_underflow(
+            generated_column,
+            generated_column_offset,
+            ColumnUnderflow::Error,
+        )
+    }
+
+    // Shift every mapping at or after generated_column, underflow decides what happens
+    // when mappings would end up before column 0
+    pub fn offset_columns_with_underflow(
+        &mut self,
+        generated_column: u32,
+        generated_column_offset: i64,
+        underflow: ColumnUnderflow,
+    ) -> Result<usize, SourceMapError> {
         let (start_column, overflowed) =
             (generated_column as i64).overflowing_add(generated_column_offset);
         if overflowed || start_column > (u32::MAX as i64) {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::UnexpectedlyBigNumber,
+                "column + column_offset is too big",
+            ));
+        }
+
+        if start_column < 0 && underflow == ColumnUnderflow::Error {
             return Err(SourceMapError::new_with_reason(
                 SourceMapErrorType::UnexpectedNegativeNumber,
                 "column + column_offset cannot be negative",
@@ -95,7 +145,7 @@ impl MappingLine {
         };
 
         if generated_column_offset < 0 {
-            let u_start_column = start_column as u32;
+            let u_start_column = start_column.max(0) as u32;
             let start_index = match self
                 .mappings
                 .binary_search_by(|m| m.generated_column.cmp(&u_start_column))
@@ -108,16 +158,159 @@ impl MappingLine {
             index = start_index;
         }
 
-        let abs_offset = generated_column_offset.abs() as u32;
-        for i in index..self.mappings.len() {
-            let mapping = &mut self.mappings[i];
-            mapping.generated_column = if generated_column_offset < 0 {
-                mapping.generated_column - abs_offset
-            } else {
-                mapping.generated_column + abs_offset
+        if start_column < 0 {
+            // Mappings that would end up before column 0
+            let underflowing = self.mappings[index..]
+                .iter()
+                .take_while(|m| (m.generated_column as i64) + generated_column_offset < 0)
+                .count();
+            let lands_on_zero = matches!(
+                self.mappings.get(index + underflowing),
+                Some(m) if (m.generated_column as i64) + generated_column_offset == 0
+            );
+            let dropped = match underflow {
+                // The last underflowing mapping covers column 0, unless a mapping already lands there
+                ColumnUnderflow::Saturate if !lands_on_zero => underflowing.saturating_sub(1),
+                _ => underflowing,
             };
+            self.mappings.drain(index..index + dropped);
+        }
+
+        for mapping in self.mappings[index..].iter_mut() {
+            mapping.generated_column =
+                ((mapping.generated_column as i64) + generated_column_offset).max(0) as u32;
+        }
+
+        Ok(self.mappings.len() - index)
+    }
+
+    // Merge the mappings of another line into this one,
+    // if both lines have a mapping on the same column the mapping of the other line wins
+    pub fn merge(&mut self, mut other: MappingLine) {
+        if other.mappings.is_empty() {
+            return;
+        }
+
+        if self.mappings.is_empty() {
+            *self = other;
+            return;
         }
 
-        Ok(())
+        self.ensure_sorted();
+        other.ensure_sorted();
+        let mut merged = Vec::with_capacity(self.mappings.len() + other.mappings.len());
+        let mut existing = std::mem::take(&mut self.mappings).into_iter().peekable();
+        for mapping in other.mappings {
+            while let Some(existing_mapping) = existing.peek() {
+                if existing_mapping.generated_column > mapping.generated_column {
+                    break;
+                }
+
+                if existing_mapping.generated_column < mapping.generated_column {
+                    merged.push(*existing_mapping);
+                }
+                existing.next();
+            }
+            merged.push(mapping);
+        }
+        merged.extend(existing);
+
+        self.last_column = merged.last().map(|m| m.generated_column).unwrap_or(0);
+        self.mappings = merged;
+        self.is_sorted = true;
     }
 }
+
+#[test]
+fn test_merge_disjoint() {
+    let mut line = MappingLine::new();
+    line.add_mapping(10, None);
+    line.add_mapping(0, None);
+    let mut other = MappingLine::new();
+    other.add_mapping(5, Some(OriginalLocation::new(1, 0, 0, None)));
+    other.add_mapping(20, Some(OriginalLocation::new(2, 0, 0, None)));
+
+    line.merge(other);
+    let columns: Vec<u32> = line.mappings.iter().map(|m| m.generated_column).collect();
+    assert_eq!(columns, vec![0, 5, 10, 20]);
+    assert!(line.is_sorted);
+    assert_eq!(line.last_column, 20);
+    assert_eq!(line.mappings[1].original.unwrap().original_line, 1);
+
+    let mut empty_line = MappingLine::new();
+    let mut other = MappingLine::new();
+    other.add_mapping(3, None);
+    empty_line.merge(other);
+    assert_eq!(empty_line.mappings.len(), 1);
+    assert_eq!(empty_line.mappings[0].generated_column, 3);
+}
+
+#[test]
+fn test_merge_overlapping() {
+    let mut line = MappingLine::new();
+    for column in [0, 5, 10].iter() {
+        line.add_mapping(*column, Some(OriginalLocation::new(0, 0, 0, None)));
+    }
+    let mut other = MappingLine::new();
+    for column in [5, 10, 15].iter() {
+        other.add_mapping(*column, Some(OriginalLocation::new(1, 0, 0, None)));
+    }
+
+    line.merge(other);
+    let mappings: Vec<(u32, u32)> = line
+        .mappings
+        .iter()
+        .map(|m| (m.generated_column, m.original.unwrap().original_line))
+        .collect();
+    assert_eq!(mappings, vec![(0, 0), (5, 1), (10, 1), (15, 1)]);
+}
+
+#[test]
+fn test_offset_columns_underflow() {
+    let build = || {
+        let mut line = MappingLine::new();
+        for column in [2, 5, 12, 20].iter() {
+            line.add_mapping(*column, Some(OriginalLocation::new(0, *column, 0, None)));
+        }
+        line
+    };
+    let columns = |line: &MappingLine| -> Vec<(u32, u32)> {
+        line.columns()
+            .map(|m| (m.generated_column, m.original.unwrap().original_column))
+            .collect()
+    };
+
+    let mut line = build();
+    let err = line
+        .offset_columns_with_underflow(5, -8, ColumnUnderflow::Error)
+        .unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        SourceMapErrorType::UnexpectedNegativeNumber
+    ));
+    assert_eq!(columns(&line), vec![(2, 2), (5, 5), (12, 12), (20, 20)]);
+
+    let mut line = build();
+    assert_eq!(
+        line.offset_columns_with_underflow(5, -8, ColumnUnderflow::Saturate)
+            .unwrap(),
+        3
+    );
+    assert_eq!(columns(&line), vec![(0, 5), (4, 12), (12, 20)]);
+
+    let mut line = build();
+    assert_eq!(
+        line.offset_columns_with_underflow(5, -12, ColumnUnderflow::Saturate)
+            .unwrap(),
+        2
+    );
+    assert_eq!(columns(&line), vec![(0, 12), (8, 20)]);
+
+    let mut line = build();
+    assert_eq!(
+        line.offset_columns_with_underflow(5, -8, ColumnUnderflow::Drop)
+            .unwrap(),
+        2
+    );
+    assert_eq!(columns(&line), vec![(4, 12), (12, 20)]);
+}
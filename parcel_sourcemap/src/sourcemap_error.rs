@@ -37,8 +37,20 @@ pub enum SourceMapErrorType {
 
     // Failed to convert utf-8 to array
     FromUtf8Error = 11,
+
+    // A mapping points outside of the generated code or source content
+    MappingOutOfRange = 12,
+
+    // The sourcemap has sources but no mappings
+    NoMappings = 13,
+
+    // A mapping or an operation on the mappings is inconsistent, e.g. unsorted or duplicate columns,
+    // lines written out of order, overlapping offsets or a mapping that changed after a VLQ roundtrip.
+    // The reason describes the specific problem.
+    InvalidMapping = 14,
 }
 
+#[derive(Debug)]
 pub struct SourceMapError {
     pub error_type: SourceMapErrorType,
     pub reason: Option<String>,
@@ -121,6 +133,15 @@ impl From<SourceMapError> for napi::Error {
             SourceMapErrorType::FromUtf8Error => {
                 reason.push_str("Could not convert utf-8 array to string");
             }
+            SourceMapErrorType::MappingOutOfRange => {
+                reason.push_str("Mapping out of range");
+            }
+            SourceMapErrorType::NoMappings => {
+                reason.push_str("Sourcemap has sources but no mappings");
+            }
+            SourceMapErrorType::InvalidMapping => {
+                reason.push_str("Invalid mapping");
+            }
         }
 
         // Add reason to error string if there is one
@@ -176,6 +197,15 @@ impl From<SourceMapError> for wasm_bindgen::JsValue {
             SourceMapErrorType::FromUtf8Error => {
                 reason.push_str("Could not convert utf-8 array to string");
             }
+            SourceMapErrorType::MappingOutOfRange => {
+                reason.push_str("Mapping out of range");
+            }
+            SourceMapErrorType::NoMappings => {
+                reason.push_str("Sourcemap has sources but no mappings");
+            }
+            SourceMapErrorType::InvalidMapping => {
+                reason.push_str("Invalid mapping");
+            }
         }
 
         // Add reason to error string if there is one
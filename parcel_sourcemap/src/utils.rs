@@ -1,6 +1,7 @@
 // Based on https://github.com/getsentry/rust-sourcemap/blob/master/src/utils.rs
 use std::borrow::Cow;
 use std::iter::repeat;
+use std::path::{Component, PathBuf};
 
 pub fn is_abs_path(s: &str) -> bool {
     if s.starts_with('/') || s.starts_with('\\') {
@@ -83,6 +84,168 @@ pub fn make_relative_path(base: &str, target: &str) -> String {
     }
 }
 
+// Helper function to resolve a relative target path against a base directory,
+// absolute paths and urls are returned as is.
+pub fn make_absolute_path(base: &str, target: &str) -> String {
+    if is_abs_path(target) || target.contains(':') {
+        return String::from(target);
+    }
+
+    let mut result = PathBuf::from(base);
+    for component in PathBuf::from(target).components() {
+        match component {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::Normal(part) => result.push(part),
+            _ => {}
+        }
+    }
+    result.to_string_lossy().into_owned()
+}
+
+// Convert an absolute filepath into a file:// url, percent-encoding any characters that are not allowed in a url path
+pub fn file_url_from_path(path: &str) -> String {
+    let normalized_path = path.replace('\\', "/");
+    let mut url = String::from("file://");
+    if !normalized_path.starts_with('/') {
+        // Windows paths like C:/foo need an extra slash, file:///C:/foo
+        url.push('/');
+    }
+
+    for byte in normalized_path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() / 4 * 3);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in input.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+fn decode_percent(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3)?;
+            output.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            output.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(output).ok()
+}
+
+// Find the url of the last sourceMappingURL comment in the generated code,
+// inline data urls get decoded and the sourcemap JSON is returned instead
+pub fn extract_source_mapping_url(generated: &str) -> Option<String> {
+    let url = generated.lines().rev().find_map(|line| {
+        let line = line.trim();
+        let comment = line
+            .strip_prefix("//")
+            .or_else(|| line.strip_prefix("/*"))?;
+        let url = comment
+            .strip_prefix("# sourceMappingURL=")
+            .or_else(|| comment.strip_prefix("@ sourceMappingURL="))?;
+        let url = if line.starts_with("/*") {
+            url.strip_suffix("*/")?
+        } else {
+            url
+        };
+        Some(url.trim())
+    })?;
+
+    if url.is_empty() {
+        return None;
+    }
+
+    match url.strip_prefix("data:") {
+        Some(data_url) => {
+            let (media_type, data) = data_url.split_at(data_url.find(',')?);
+            let data = &data[1..];
+            if media_type.ends_with(";base64") {
+                String::from_utf8(decode_base64(data)?).ok()
+            } else {
+                decode_percent(data)
+            }
+        }
+        None => Some(String::from(url)),
+    }
+}
+
+// The comment that links generated code to its sourcemap
+pub fn source_mapping_url_comment(url: &str) -> String {
+    format!("//# sourceMappingURL={}", url)
+}
+
+// Collapse the `.` and `..` segments of a path or of the path part of a url without touching the filesystem,
+// leading `..` segments of a relative path are kept as there is nothing to resolve them against
+pub fn normalize_path(path: &str) -> String {
+    let (prefix, path) = match path.find("://") {
+        Some(scheme_end) => {
+            let authority_start = scheme_end + 3;
+            let path_start = path[authority_start..]
+                .find('/')
+                .map(|i| authority_start + i)
+                .unwrap_or_else(|| path.len());
+            path.split_at(path_start)
+        }
+        None => ("", path),
+    };
+
+    let is_absolute = path.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if matches!(segments.last(), Some(last) if *last != "..") {
+                    segments.pop();
+                } else if !is_absolute && prefix.is_empty() {
+                    segments.push(segment);
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    let mut normalized = String::from(prefix);
+    if is_absolute {
+        normalized.push('/');
+    }
+    normalized.push_str(&segments.join("/"));
+    normalized
+}
+
 #[test]
 fn test_make_relative_path() {
     assert_eq!(
@@ -104,3 +267,100 @@ fn test_make_relative_path() {
     );
     assert_eq!(&make_relative_path("/", "./test.js"), "test.js");
 }
+
+#[test]
+fn test_make_absolute_path() {
+    assert_eq!(&make_absolute_path("/foo/bar", "baz.js"), "/foo/bar/baz.js");
+    assert_eq!(
+        &make_absolute_path("/foo/bar", "./baz.js"),
+        "/foo/bar/baz.js"
+    );
+    assert_eq!(&make_absolute_path("/foo/bar", "../baz.js"), "/foo/baz.js");
+    assert_eq!(
+        &make_absolute_path("/foo/bar", "/other/baz.js"),
+        "/other/baz.js"
+    );
+    assert_eq!(
+        &make_absolute_path("/foo/bar", "http://example.com/baz.js"),
+        "http://example.com/baz.js"
+    );
+}
+
+#[test]
+fn test_file_url_from_path() {
+    assert_eq!(&file_url_from_path("/foo/bar.js"), "file:///foo/bar.js");
+    assert_eq!(
+        &file_url_from_path("/foo bar/baz#1.js"),
+        "file:///foo%20bar/baz%231.js"
+    );
+    assert_eq!(
+        &file_url_from_path("C:\\foo\\bar baz.js"),
+        "file:///C:/foo/bar%20baz.js"
+    );
+}
+
+#[test]
+fn test_extract_source_mapping_url() {
+    assert_eq!(
+        extract_source_mapping_url("foo();\n//# sourceMappingURL=foo.js.map\n"),
+        Some(String::from("foo.js.map"))
+    );
+    assert_eq!(
+        extract_source_mapping_url(
+            "//# sourceMappingURL=old.js.map\nfoo();\n//# sourceMappingURL=new.js.map"
+        ),
+        Some(String::from("new.js.map"))
+    );
+    assert_eq!(
+        extract_source_mapping_url("body{}\n/*# sourceMappingURL=style.css.map */"),
+        Some(String::from("style.css.map"))
+    );
+    assert_eq!(
+        extract_source_mapping_url("foo();\n//@ sourceMappingURL=legacy.js.map"),
+        Some(String::from("legacy.js.map"))
+    );
+    assert_eq!(
+        extract_source_mapping_url(
+            "foo();\n//# sourceMappingURL=data:application/json;charset=utf-8;base64,eyJ2ZXJzaW9uIjozfQ=="
+        ),
+        Some(String::from("{\"version\":3}"))
+    );
+    assert_eq!(
+        extract_source_mapping_url(
+            "foo();\n//# sourceMappingURL=data:application/json,%7B%22version%22%3A3%7D"
+        ),
+        Some(String::from("{\"version\":3}"))
+    );
+    assert_eq!(extract_source_mapping_url("foo();\n"), None);
+    assert_eq!(
+        extract_source_mapping_url("//# sourceMappingURL=data:application/json;base64,!!"),
+        None
+    );
+}
+
+#[test]
+fn test_source_mapping_url_comment() {
+    let comment = source_mapping_url_comment("foo.js.map");
+    assert_eq!(comment, "//# sourceMappingURL=foo.js.map");
+    assert_eq!(
+        extract_source_mapping_url(comment.as_str()),
+        Some(String::from("foo.js.map"))
+    );
+}
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!(normalize_path("./src/../src/a.js"), "src/a.js");
+    assert_eq!(normalize_path("src/./lib//a.js"), "src/lib/a.js");
+    assert_eq!(normalize_path("../../a/../b.js"), "../../b.js");
+    assert_eq!(normalize_path("/root/../../a.js"), "/a.js");
+    assert_eq!(
+        normalize_path("https://example.com/a/./b/../c.js"),
+        "https://example.com/a/c.js"
+    );
+    assert_eq!(
+        normalize_path("webpack:///./src/../a.js"),
+        "webpack:///a.js"
+    );
+    assert_eq!(normalize_path("https://example.com"), "https://example.com");
+}
@@ -1,6 +1,7 @@
 // Based on https://github.com/fitzgen/source-map-mappings
 use crate::sourcemap_error::{SourceMapError, SourceMapErrorType};
-use vlq::decode;
+use std::io::{self, Read};
+use vlq::{decode, encode};
 
 #[inline]
 pub fn read_relative_vlq<B>(previous: &mut i64, input: &mut B) -> Result<(), SourceMapError>
@@ -26,7 +27,81 @@ where
     Ok(())
 }
 
+// Encode a single signed integer as a base64 VLQ
+pub fn encode_vlq(value: i64) -> String {
+    let mut output: Vec<u8> = Vec::with_capacity(8);
+    // Writing into a Vec can't fail and the encoded bytes are always base64 characters
+    encode(value, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+// Decode a string of consecutive base64 VLQs into the signed integers it contains
+pub fn decode_vlq(input: &str) -> Result<Vec<i64>, SourceMapError> {
+    let mut values = Vec::new();
+    let mut bytes = input.bytes().peekable();
+    while bytes.peek().is_some() {
+        values.push(decode(&mut bytes)?);
+    }
+    Ok(values)
+}
+
 #[inline]
 pub fn is_mapping_separator(byte: u8) -> bool {
     byte == b';' || byte == b','
 }
+
+// Iterator over the bytes of a reader, stops at the first io error and keeps it around
+// so it can be reported once parsing is done.
+pub struct ReadBytes<R: io::Read> {
+    bytes: io::Bytes<io::BufReader<R>>,
+    pub error: Option<io::Error>,
+}
+
+impl<R: io::Read> ReadBytes<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            bytes: io::BufReader::new(reader).bytes(),
+            error: None,
+        }
+    }
+}
+
+impl<R: io::Read> Iterator for ReadBytes<R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        match self.bytes.next()? {
+            Ok(byte) => Some(byte),
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+#[test]
+fn test_encode_vlq() {
+    assert_eq!(encode_vlq(0), "A");
+    assert_eq!(encode_vlq(1), "C");
+    assert_eq!(encode_vlq(-1), "D");
+    assert_eq!(encode_vlq(16), "gB");
+    assert_eq!(encode_vlq(-16), "hB");
+}
+
+#[test]
+fn test_decode_vlq() {
+    assert_eq!(decode_vlq("A").unwrap(), vec![0]);
+    assert_eq!(decode_vlq("CDgBhB").unwrap(), vec![1, -1, 16, -16]);
+    assert_eq!(decode_vlq("").unwrap(), Vec::<i64>::new());
+    assert!(decode_vlq("g").is_err());
+    assert!(decode_vlq("!").is_err());
+
+    for value in [0, 1, -1, 123456, -123456].iter() {
+        assert_eq!(decode_vlq(&encode_vlq(*value)).unwrap(), vec![*value]);
+    }
+}
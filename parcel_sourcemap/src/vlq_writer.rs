@@ -0,0 +1,118 @@
+use crate::mapping_line::{LineMapping, MappingLine};
+use crate::sourcemap_error::{SourceMapError, SourceMapErrorType};
+use std::io;
+
+// Holds the VLQ delta state between lines so mappings can be emitted one line at a time,
+// writing consecutive lines (in order) produces the same output as a single write_vlq call
+#[derive(Debug, Clone, Default)]
+pub struct VlqWriter {
+    base_line: u32,
+    last_generated_line: Option<u32>,
+    previous_source: i64,
+    previous_original_line: i64,
+    previous_original_column: i64,
+    previous_name: i64,
+}
+
+impl VlqWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Lines are written relative to base_line, base_line is written as if it was the first line
+    pub fn with_base_line(base_line: u32) -> Self {
+        Self {
+            base_line,
+            ..Self::default()
+        }
+    }
+
+    // Write the mappings of a single generated line, lines have to be written in increasing order
+    pub fn write_line<W>(
+        &mut self,
+        generated_line: u32,
+        line: &MappingLine,
+        output: &mut W,
+    ) -> Result<(), SourceMapError>
+    where
+        W: io::Write,
+    {
+        let previous_line = self.last_generated_line.unwrap_or(self.base_line);
+        if generated_line < self.base_line
+            || (self.last_generated_line.is_some() && generated_line <= previous_line)
+        {
+            return Err(SourceMapError::new_with_reason(
+                SourceMapErrorType::InvalidMapping,
+                "Lines have to be written in increasing order",
+            ));
+        }
+
+        // Write a ';' for each line between this and last line, way more efficient than storing empty lines or looping...
+        output.write_all(&b";".repeat((generated_line - previous_line) as usize))?;
+
+        if line.is_sorted {
+            self.write_mappings(&line.mappings, output)?;
+        } else {
+            let mut sorted = line.mappings.clone();
+            sorted.sort_by_key(|m| m.generated_column);
+            self.write_mappings(&sorted, output)?;
+        }
+
+        self.last_generated_line = Some(generated_line);
+        Ok(())
+    }
+
+    fn write_mappings<W>(
+        &mut self,
+        mappings: &[LineMapping],
+        output: &mut W,
+    ) -> Result<(), SourceMapError>
+    where
+        W: io::Write,
+    {
+        let mut previous_generated_column: u32 = 0;
+        let mut is_first_mapping = true;
+        for (index, mapping) in mappings.iter().enumerate() {
+            // Mappings on the same column can end up in a line through repeated add_mapping calls or merges,
+            // only the last one is written so columns in the output are strictly increasing
+            if matches!(mappings.get(index + 1), Some(next) if next.generated_column == mapping.generated_column)
+            {
+                continue;
+            }
+
+            if !is_first_mapping {
+                output.write_all(b",")?;
+            }
+            is_first_mapping = false;
+
+            vlq::encode(
+                (mapping.generated_column - previous_generated_column) as i64,
+                output,
+            )?;
+            previous_generated_column = mapping.generated_column;
+
+            // Source should only be written if there is any
+            if let Some(original) = &mapping.original {
+                let original_source = original.source as i64;
+                vlq::encode(original_source - self.previous_source, output)?;
+                self.previous_source = original_source;
+
+                let original_line = original.original_line as i64;
+                vlq::encode(original_line - self.previous_original_line, output)?;
+                self.previous_original_line = original_line;
+
+                let original_column = original.original_column as i64;
+                vlq::encode(original_column - self.previous_original_column, output)?;
+                self.previous_original_column = original_column;
+
+                if let Some(name) = original.name {
+                    let original_name = name as i64;
+                    vlq::encode(original_name - self.previous_name, output)?;
+                    self.previous_name = original_name;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -8,7 +8,7 @@ use napi::{
     CallContext, Either, Env, JsBuffer, JsNull, JsNumber, JsObject, JsString, JsTypedArray,
     JsUndefined, Property, Result,
 };
-use parcel_sourcemap::{Mapping, OriginalLocation, SourceMap};
+use parcel_sourcemap::{Mapping, SourceMap};
 use rkyv::AlignedVec;
 use serde_json::{from_str, to_string};
 
@@ -306,6 +306,9 @@ fn to_vlq(ctx: CallContext) -> Result<JsObject> {
     result_obj.set_named_property("sources", _get_sources(&ctx)?)?;
     result_obj.set_named_property("sourcesContent", _get_sources_content(&ctx)?)?;
     result_obj.set_named_property("names", _get_names(&ctx)?)?;
+    if let Some(debug_id) = source_map_instance.get_debug_id() {
+        result_obj.set_named_property("debugId", ctx.env.create_string(debug_id)?)?;
+    }
 
     Ok(result_obj)
 }
@@ -318,50 +321,7 @@ fn add_indexed_mappings(ctx: CallContext) -> Result<JsUndefined> {
     let mappings = ctx.get::<JsTypedArray>(0)?;
     let mappings_value = mappings.into_value()?;
     let mappings_arr: &[i32] = mappings_value.as_ref();
-    let mappings_count = mappings_arr.len();
-
-    let mut generated_line: u32 = 0; // 0
-    let mut generated_column: u32 = 0; // 1
-    let mut original_line: i32 = 0; // 2
-    let mut original_column: i32 = 0; // 3
-    let mut original_source: i32 = 0; // 4
-    for (i, value) in mappings_arr.iter().enumerate().take(mappings_count) {
-        let value = *value;
-        match i % 6 {
-            0 => {
-                generated_line = value as u32;
-            }
-            1 => {
-                generated_column = value as u32;
-            }
-            2 => {
-                original_line = value;
-            }
-            3 => {
-                original_column = value;
-            }
-            4 => {
-                original_source = value;
-            }
-            5 => {
-                source_map_instance.add_mapping(
-                    generated_line,
-                    generated_column,
-                    if original_line > -1 && original_column > -1 && original_source > -1 {
-                        Some(OriginalLocation {
-                            original_line: original_line as u32,
-                            original_column: original_column as u32,
-                            source: original_source as u32,
-                            name: if value > -1 { Some(value as u32) } else { None },
-                        })
-                    } else {
-                        None
-                    },
-                );
-            }
-            _ => unreachable!(),
-        }
-    }
+    source_map_instance.add_indexed_mappings(mappings_arr);
 
     ctx.env.get_undefined()
 }
@@ -430,6 +390,24 @@ fn find_closest_mapping(ctx: CallContext) -> Result<Either<JsObject, JsNull>> {
     }
 }
 
+#[js_function]
+fn get_stats(ctx: CallContext) -> Result<JsObject> {
+    let this: JsObject = ctx.this_unchecked();
+    let source_map_instance: &SourceMap = ctx.env.unwrap(&this)?;
+
+    let stats = source_map_instance.stats();
+    let mut stats_obj = ctx.env.create_object()?;
+    stats_obj.set_named_property("sources", ctx.env.create_uint32(stats.sources as u32)?)?;
+    stats_obj.set_named_property("names", ctx.env.create_uint32(stats.names as u32)?)?;
+    stats_obj.set_named_property("mappings", ctx.env.create_uint32(stats.mappings as u32)?)?;
+    stats_obj.set_named_property("lines", ctx.env.create_uint32(stats.lines as u32)?)?;
+    stats_obj.set_named_property(
+        "withContent",
+        ctx.env.create_uint32(stats.with_content as u32)?,
+    )?;
+    Ok(stats_obj)
+}
+
 #[js_function]
 fn get_project_root(ctx: CallContext) -> Result<JsString> {
     let this: JsObject = ctx.this_unchecked();
@@ -440,6 +418,32 @@ fn get_project_root(ctx: CallContext) -> Result<JsString> {
         .create_string(source_map_instance.project_root.as_str());
 }
 
+#[js_function]
+fn get_debug_id(ctx: CallContext) -> Result<Either<JsString, JsNull>> {
+    let this: JsObject = ctx.this_unchecked();
+    let source_map_instance: &mut SourceMap = ctx.env.unwrap(&this)?;
+
+    match source_map_instance.get_debug_id() {
+        Some(debug_id) => ctx.env.create_string(debug_id).map(Either::A),
+        None => ctx.env.get_null().map(Either::B),
+    }
+}
+
+#[js_function(1)]
+fn set_debug_id(ctx: CallContext) -> Result<JsUndefined> {
+    let this: JsObject = ctx.this_unchecked();
+    let source_map_instance: &mut SourceMap = ctx.env.unwrap(&this)?;
+
+    match ctx.get::<Either<JsString, JsNull>>(0)? {
+        Either::A(debug_id) => {
+            let debug_id = debug_id.into_utf8()?;
+            source_map_instance.set_debug_id(Some(debug_id.as_str()?));
+        }
+        Either::B(_) => source_map_instance.set_debug_id(None),
+    }
+    ctx.env.get_undefined()
+}
+
 #[js_function(2)]
 fn constructor(ctx: CallContext) -> Result<JsUndefined> {
     let mut this: JsObject = ctx.this_unchecked();
@@ -491,6 +495,9 @@ fn init(mut exports: JsObject, env: Env) -> Result<()> {
         Property::new(&env, "getProjectRoot")?.with_method(get_project_root);
     let find_closest_mapping_method =
         Property::new(&env, "findClosestMapping")?.with_method(find_closest_mapping);
+    let get_stats_method = Property::new(&env, "getStats")?.with_method(get_stats);
+    let get_debug_id_method = Property::new(&env, "getDebugId")?.with_method(get_debug_id);
+    let set_debug_id_method = Property::new(&env, "setDebugId")?.with_method(set_debug_id);
     let sourcemap_class = env.define_class(
         "SourceMap",
         constructor,
@@ -518,6 +525,9 @@ fn init(mut exports: JsObject, env: Env) -> Result<()> {
             extends_method,
             find_closest_mapping_method,
             get_project_root_method,
+            get_stats_method,
+            get_debug_id_method,
+            set_debug_id_method,
         ],
     )?;
     exports.set_named_property("SourceMap", sourcemap_class)?;
@@ -3,7 +3,7 @@
 extern crate parcel_sourcemap;
 
 use js_sys::Uint8Array;
-use parcel_sourcemap::{Mapping, OriginalLocation, SourceMap as NativeSourceMap};
+use parcel_sourcemap::{Mapping, SourceMap as NativeSourceMap};
 use rkyv::AlignedVec;
 use serde::Serialize;
 use std::convert::TryFrom;
@@ -16,6 +16,18 @@ struct VLQResult {
     sources: Vec<String>,
     sourcesContent: Vec<String>,
     names: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debugId: Option<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize)]
+struct StatsResult {
+    sources: usize,
+    names: usize,
+    mappings: usize,
+    lines: usize,
+    withContent: usize,
 }
 
 #[derive(Serialize)]
@@ -81,6 +93,14 @@ impl SourceMap {
         self.map.project_root.clone()
     }
 
+    pub fn getDebugId(&self) -> Option<String> {
+        self.map.get_debug_id().map(String::from)
+    }
+
+    pub fn setDebugId(&mut self, debug_id: Option<String>) {
+        self.map.set_debug_id(debug_id.as_deref());
+    }
+
     pub fn addVLQMap(
         &mut self,
         vlq_mappings: String,
@@ -114,6 +134,7 @@ impl SourceMap {
             sources: self.map.get_sources().clone(),
             sourcesContent: self.map.get_sources_content().clone(),
             names: self.map.get_names().clone(),
+            debugId: self.map.get_debug_id().map(String::from),
         };
         Ok(JsValue::from_serde(&result).unwrap())
     }
@@ -145,6 +166,18 @@ impl SourceMap {
         Ok(JsValue::from_serde(&self.map.get_sources_content()).unwrap())
     }
 
+    pub fn getStats(&self) -> Result<JsValue, JsValue> {
+        let stats = self.map.stats();
+        let result = StatsResult {
+            sources: stats.sources,
+            names: stats.names,
+            mappings: stats.mappings,
+            lines: stats.lines,
+            withContent: stats.with_content,
+        };
+        Ok(JsValue::from_serde(&result).unwrap())
+    }
+
     pub fn getNames(&self) -> Result<JsValue, JsValue> {
         Ok(JsValue::from_serde(&self.map.get_names()).unwrap())
     }
@@ -182,50 +215,7 @@ impl SourceMap {
     }
 
     pub fn addIndexedMappings(&mut self, mappings_arr: &[i32]) {
-        let mappings_count = mappings_arr.len();
-        let mut generated_line: u32 = 0; // 0
-        let mut generated_column: u32 = 0; // 1
-        let mut original_line: i32 = 0; // 2
-        let mut original_column: i32 = 0; // 3
-        let mut original_source: i32 = 0; // 4
-        for (i, item) in mappings_arr.iter().enumerate().take(mappings_count) {
-            let value = *item;
-
-            match i % 6 {
-                0 => {
-                    generated_line = value as u32;
-                }
-                1 => {
-                    generated_column = value as u32;
-                }
-                2 => {
-                    original_line = value;
-                }
-                3 => {
-                    original_column = value;
-                }
-                4 => {
-                    original_source = value;
-                }
-                5 => {
-                    self.map.add_mapping(
-                        generated_line,
-                        generated_column,
-                        if original_line > -1 && original_column > -1 && original_source > -1 {
-                            Some(OriginalLocation {
-                                original_line: original_line as u32,
-                                original_column: original_column as u32,
-                                source: original_source as u32,
-                                name: if value > -1 { Some(value as u32) } else { None },
-                            })
-                        } else {
-                            None
-                        },
-                    );
-                }
-                _ => unreachable!(),
-            }
-        }
+        self.map.add_indexed_mappings(mappings_arr);
     }
 
     pub fn toBuffer(&self) -> Result<JsValue, JsValue> {
@@ -1,5 +1,13 @@
 // @flow
-import type { ParsedMap, VLQMap, SourceMapStringifyOptions, IndexedMapping, GenerateEmptyMapOptions } from './types';
+import type {
+  ParsedMap,
+  VLQMap,
+  SourceMapStringifyOptions,
+  IndexedMapping,
+  GenerateEmptyMapOptions,
+  SourceMapStats,
+  SplitSourceMap,
+} from './types';
 
 import path from 'path';
 import { generateInlineMap, partialVlqMapToSourceMap } from './utils';
@@ -356,6 +364,29 @@ export default class SourceMap {
     };
   }
 
+  /**
+   * Returns the amount of sources, names, mappings, generated lines and sources with inlined content
+   */
+  getStats(): SourceMapStats {
+    return this.sourceMapInstance.getStats();
+  }
+
+  /**
+   * Returns the debug id of this sourcemap or null if it doesn't have one
+   */
+  getDebugId(): string | null {
+    return this.sourceMapInstance.getDebugId();
+  }
+
+  /**
+   * Set the debug id (usually a UUID) that links the generated code to this sourcemap, null removes it
+   *
+   * @param debugId the debug id, it's stored as is
+   */
+  setDebugId(debugId: string | null): void {
+    this.sourceMapInstance.setDebugId(debugId);
+  }
+
   /**
    * Searches through the sourcemap and returns a mapping that is close to the provided generated line and column
    *
@@ -441,4 +472,39 @@ export default class SourceMap {
       rootDir: this.projectRoot || options.rootDir,
     });
   }
+
+  /**
+   * Returns a serialised map without embedded sources content, together with the content of each source
+   * so it can be stored or served separately from the map
+   *
+   * @param options options used for formatting the serialised map
+   */
+  async stringifySplit(options: SourceMapStringifyOptions): Promise<SplitSourceMap> {
+    let map: any = await partialVlqMapToSourceMap(this.toVLQ(), {
+      ...options,
+      rootDir: this.projectRoot || options.rootDir,
+      format: 'object',
+    });
+
+    let sourcesContent = map.sources.map((source, index) => [
+      source,
+      (map.sourcesContent && map.sourcesContent[index]) || null,
+    ]);
+    if (options.omitEmpty) {
+      delete map.sourcesContent;
+    } else {
+      map.sourcesContent = null;
+    }
+
+    let format = options.format || 'string';
+    if (format === 'object') {
+      return { map, sourcesContent };
+    }
+
+    let stringifiedMap = JSON.stringify(map);
+    return {
+      map: format === 'inline' ? generateInlineMap(stringifiedMap) : stringifiedMap,
+      sourcesContent,
+    };
+  }
 }
@@ -27,6 +27,9 @@ export default class NodeSourceMap extends SourceMap {
       lineOffset,
       columnOffset
     );
+    if (map.debugId && !this.getDebugId()) {
+      this.setDebugId(map.debugId);
+    }
     return this;
   }
 
@@ -27,6 +27,7 @@ export type VLQMap = {
   +version?: number,
   +file?: string,
   +sourceRoot?: string,
+  +debugId?: string,
   ...
 };
 
@@ -36,6 +37,7 @@ export type SourceMapStringifyOptions = {
   inlineSources?: boolean,
   fs?: { readFile(path: string, encoding: string): Promise<string>, ... },
   format?: 'inline' | 'string' | 'object',
+  omitEmpty?: boolean,
   /**
    * @private
    */
@@ -43,6 +45,11 @@ export type SourceMapStringifyOptions = {
   ...
 };
 
+export type SplitSourceMap = {|
+  map: string | VLQMap,
+  sourcesContent: Array<[string, string | null]>,
+|};
+
 export type GenerateEmptyMapOptions = {
   projectRoot: string,
   sourceName: string,
@@ -50,3 +57,11 @@ export type GenerateEmptyMapOptions = {
   lineOffset?: number,
   ...
 };
+
+export type SourceMapStats = {|
+  sources: number,
+  names: number,
+  mappings: number,
+  lines: number,
+  withContent: number,
+|};
@@ -7,7 +7,7 @@ export function generateInlineMap(map: string): string {
 }
 
 export async function partialVlqMapToSourceMap(map: VLQMap, opts: SourceMapStringifyOptions): Promise<VLQMap | string> {
-  let { fs, file, sourceRoot, inlineSources, rootDir, format = 'string' } = opts;
+  let { fs, file, sourceRoot, inlineSources, rootDir, omitEmpty, format = 'string' } = opts;
 
   let resultMap = {
     ...map,
@@ -48,6 +48,21 @@ export async function partialVlqMapToSourceMap(map: VLQMap, opts: SourceMapStrin
     );
   }
 
+  // These fields are optional in v3 sourcemaps, so they can be left out entirely when they don't contain anything
+  if (omitEmpty) {
+    if (resultMap.names.length === 0) {
+      delete resultMap.names;
+    }
+
+    if (resultMap.sourcesContent.every((content) => !content)) {
+      delete resultMap.sourcesContent;
+    }
+
+    if (!resultMap.sourceRoot) {
+      delete resultMap.sourceRoot;
+    }
+  }
+
   if (format === 'inline' || format === 'string') {
     let stringifiedMap = JSON.stringify(resultMap);
     if (format === 'inline') {
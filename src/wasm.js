@@ -29,6 +29,9 @@ export default class WasmSourceMap extends SourceMap {
       lineOffset,
       columnOffset
     );
+    if (map.debugId && !this.getDebugId()) {
+      this.setDebugId(map.debugId);
+    }
     return this;
   }
 
@@ -141,6 +141,33 @@ describe('SourceMap - Basics', () => {
     });
   });
 
+  it('Should return mappings sorted by generated line and column', () => {
+    let map = new SourceMap('/test-root');
+    let positions = [
+      [3, 12],
+      [1, 20],
+      [3, 2],
+      [1, 4],
+      [2, 0],
+    ];
+    map.addIndexedMappings(
+      positions.map(([line, column]) => ({
+        source: 'index.js',
+        original: { line: 1, column: 0 },
+        generated: { line, column },
+      }))
+    );
+
+    let generated = map.getMappings().map((mapping) => [mapping.generated.line, mapping.generated.column]);
+    assert.deepEqual(generated, [
+      [1, 4],
+      [1, 20],
+      [2, 0],
+      [3, 2],
+      [3, 12],
+    ]);
+  });
+
   it('Should be able to handle undefined name field using addIndexedMappings', async () => {
     let map = new SourceMap('/test-root');
 
@@ -381,6 +408,42 @@ describe('SourceMap - Basics', () => {
     assert.deepEqual(map.getNames(), ['test', 'test-two', 'test-three']);
   });
 
+  it('Should be able to return stats', () => {
+    let map = new SourceMap('/test-root');
+    map.addVLQMap({
+      mappings: SIMPLE_SOURCE_MAP.mappings,
+      sources: SIMPLE_SOURCE_MAP.sources,
+      names: SIMPLE_SOURCE_MAP.names,
+    });
+    map.addName('test');
+    map.setSourceContent('helloworld.coffee', 'module.exports = () => "hello world";');
+
+    assert.deepEqual(map.getStats(), {
+      sources: 1,
+      names: 1,
+      mappings: 11,
+      lines: 3,
+      withContent: 1,
+    });
+  });
+
+  it('Should be able to store and return a debugId', async () => {
+    let map = new SourceMap('/test-root');
+    map.addVLQMap({ ...SIMPLE_SOURCE_MAP, debugId: '85314830-023f-4cf1-a267-535f4e37bb17' });
+    assert.equal(map.getDebugId(), '85314830-023f-4cf1-a267-535f4e37bb17');
+    assert.equal(map.toVLQ().debugId, '85314830-023f-4cf1-a267-535f4e37bb17');
+
+    let stringifiedMap = JSON.parse(await map.stringify({}));
+    assert.equal(stringifiedMap.debugId, '85314830-023f-4cf1-a267-535f4e37bb17');
+
+    let copy = new SourceMap('/test-root', map.toBuffer());
+    assert.equal(copy.getDebugId(), '85314830-023f-4cf1-a267-535f4e37bb17');
+
+    map.setDebugId(null);
+    assert.equal(map.getDebugId(), null);
+    assert.equal(map.toVLQ().debugId, undefined);
+  });
+
   it('Should be able to store and return sourceContents', async () => {
     let map = new SourceMap('/test-root');
     map.addVLQMap({
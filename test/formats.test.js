@@ -106,4 +106,55 @@ describe('SourceMap - Formats', () => {
       assert.deepEqual(stringifiedMap.sources, ['helloworld.coffee']);
     });
   });
+
+  it('Should omit empty fields when omitEmpty is set', async () => {
+    let map = new SourceMap('/test-root');
+    map.addVLQMap({
+      mappings: SIMPLE_SOURCE_MAP.mappings,
+      sources: SIMPLE_SOURCE_MAP.sources,
+      names: SIMPLE_SOURCE_MAP.names,
+    });
+
+    let fullMap = await map.stringify({ file: 'index.js.map', format: 'object' });
+    assert.deepEqual(fullMap.names, []);
+    assert.deepEqual(fullMap.sourcesContent, [null]);
+
+    let compactMap = await map.stringify({ file: 'index.js.map', format: 'object', omitEmpty: true });
+    assert.deepEqual(compactMap, {
+      mappings: SIMPLE_SOURCE_MAP.mappings,
+      sources: ['helloworld.coffee'],
+      version: 3,
+      file: 'index.js.map',
+    });
+
+    for (let stringifiedMap of [fullMap, compactMap]) {
+      let reparsedMap = new SourceMap('/test-root');
+      reparsedMap.addVLQMap(JSON.parse(JSON.stringify(stringifiedMap)));
+      assert.deepEqual(reparsedMap.getMappings(), map.getMappings());
+    }
+  });
+
+  it('Should return sources content separately from the map with stringifySplit', async () => {
+    let map = new SourceMap('/test-root');
+    map.addVLQMap({
+      mappings: SIMPLE_SOURCE_MAP.mappings,
+      sources: ['helloworld.coffee', 'other.coffee'],
+      sourcesContent: ['console.log "hello world"'],
+      names: SIMPLE_SOURCE_MAP.names,
+    });
+
+    let { map: stringifiedMap, sourcesContent } = await map.stringifySplit({ file: 'helloworld.js.map' });
+    let parsedMap = JSON.parse(stringifiedMap);
+    assert.equal(parsedMap.sourcesContent, null);
+    assert.deepEqual(parsedMap.sources, ['helloworld.coffee', 'other.coffee']);
+    assert.equal(parsedMap.mappings, SIMPLE_SOURCE_MAP.mappings);
+    assert.deepEqual(sourcesContent, [
+      ['helloworld.coffee', 'console.log "hello world"'],
+      ['other.coffee', null],
+    ]);
+
+    let compact = await map.stringifySplit({ file: 'helloworld.js.map', format: 'object', omitEmpty: true });
+    assert.equal('sourcesContent' in compact.map, false);
+    assert.deepEqual(compact.sourcesContent, sourcesContent);
+  });
 });